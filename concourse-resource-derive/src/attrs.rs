@@ -0,0 +1,56 @@
+//! Parsing of the options of the derive attributes
+
+pub(crate) fn options(attrs: &[syn::Attribute], attr_name: &str) -> syn::Result<Vec<syn::Meta>> {
+    let mut options = vec![];
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident(attr_name)) {
+        match attr.parse_meta()? {
+            syn::Meta::List(list) => {
+                for nested in list.nested {
+                    match nested {
+                        syn::NestedMeta::Meta(meta) => options.push(meta),
+                        syn::NestedMeta::Lit(lit) => {
+                            return Err(syn::Error::new_spanned(lit, "expected an option"))
+                        }
+                    }
+                }
+            }
+            meta => {
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    format!("expected #[{}(...)]", attr_name),
+                ))
+            }
+        }
+    }
+    Ok(options)
+}
+
+pub(crate) fn name(meta: &syn::Meta) -> String {
+    meta.path()
+        .get_ident()
+        .map(ToString::to_string)
+        .unwrap_or_default()
+}
+
+pub(crate) fn lit_str(lit: &syn::Lit) -> syn::Result<syn::LitStr> {
+    match lit {
+        syn::Lit::Str(lit_str) => Ok(lit_str.clone()),
+        lit => Err(syn::Error::new_spanned(lit, "expected a string")),
+    }
+}
+
+pub(crate) fn unknown_option(meta: &syn::Meta, attr_name: &str, expected: &str) -> syn::Error {
+    syn::Error::new_spanned(
+        meta,
+        format!("unknown {} option, expected one of {}", attr_name, expected),
+    )
+}
+
+/// Path to the `concourse_resource` crate, `::concourse_resource` unless overridden with a
+/// `crate = "..."` option
+pub(crate) fn crate_path(krate: Option<&syn::LitStr>) -> syn::Result<syn::Path> {
+    match krate {
+        Some(krate) => krate.parse(),
+        None => Ok(syn::parse_quote!(::concourse_resource)),
+    }
+}
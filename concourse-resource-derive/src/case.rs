@@ -0,0 +1,97 @@
+//! Renaming of field names for `rename_all`
+
+/// Case to rename `snake_case` field names to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+    Title,
+}
+
+impl RenameRule {
+    /// Accepted names, the same as serde's with the addition of `Title Case`
+    const NAMES: &'static [(&'static str, RenameRule)] = &[
+        ("lowercase", RenameRule::Lower),
+        ("UPPERCASE", RenameRule::Upper),
+        ("PascalCase", RenameRule::Pascal),
+        ("camelCase", RenameRule::Camel),
+        ("snake_case", RenameRule::Snake),
+        ("SCREAMING_SNAKE_CASE", RenameRule::ScreamingSnake),
+        ("kebab-case", RenameRule::Kebab),
+        ("SCREAMING-KEBAB-CASE", RenameRule::ScreamingKebab),
+        ("Title Case", RenameRule::Title),
+    ];
+
+    pub(crate) fn from_name(name: &syn::LitStr) -> syn::Result<Self> {
+        RenameRule::NAMES
+            .iter()
+            .find(|(rule_name, _)| *rule_name == name.value())
+            .map(|(_, rule)| *rule)
+            .ok_or_else(|| {
+                let names: Vec<_> = RenameRule::NAMES
+                    .iter()
+                    .map(|(name, _)| format!("\"{}\"", name))
+                    .collect();
+                syn::Error::new_spanned(
+                    name,
+                    format!("unknown rename rule, expected one of {}", names.join(", ")),
+                )
+            })
+    }
+
+    /// Rename a `snake_case` field name
+    pub(crate) fn apply(self, field: &str) -> String {
+        let words = field.split('_').filter(|word| !word.is_empty());
+        match self {
+            RenameRule::Lower | RenameRule::Snake => field.to_ascii_lowercase(),
+            RenameRule::Upper | RenameRule::ScreamingSnake => field.to_ascii_uppercase(),
+            RenameRule::Pascal => words.map(capitalize).collect(),
+            RenameRule::Camel => {
+                let pascal = RenameRule::Pascal.apply(field);
+                let mut chars = pascal.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_ascii_lowercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            }
+            RenameRule::Kebab => field.to_ascii_lowercase().replace('_', "-"),
+            RenameRule::ScreamingKebab => field.to_ascii_uppercase().replace('_', "-"),
+            RenameRule::Title => words.map(capitalize).collect::<Vec<_>>().join(" "),
+        }
+    }
+}
+
+impl RenameRule {
+    /// Rename a `PascalCase` variant name, as serde does
+    pub(crate) fn apply_to_variant(self, variant: &str) -> String {
+        match self {
+            RenameRule::Lower => variant.to_ascii_lowercase(),
+            RenameRule::Upper => variant.to_ascii_uppercase(),
+            RenameRule::Pascal => variant.to_string(),
+            rule => {
+                let mut snake = String::new();
+                for (index, c) in variant.char_indices() {
+                    if c.is_uppercase() && index > 0 {
+                        snake.push('_');
+                    }
+                    snake.push(c.to_ascii_lowercase());
+                }
+                rule.apply(&snake)
+            }
+        }
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+        .unwrap_or_default()
+}
@@ -20,52 +20,26 @@
 extern crate proc_macro;
 
 use crate::proc_macro::TokenStream;
-use quote::quote;
 
-#[proc_macro_derive(IntoMetadataKV)]
-pub fn metadata_kv_derive(input: TokenStream) -> TokenStream {
-    // Construct a representation of Rust code as a syntax tree
-    // that we can manipulate
-    let ast: syn::DeriveInput = syn::parse(input).unwrap();
+mod attrs;
+mod case;
+mod metadata;
+mod version;
 
-    // Build the trait implementation
+#[proc_macro_derive(IntoMetadataKV, attributes(metadata))]
+pub fn metadata_kv_derive(input: TokenStream) -> TokenStream {
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
 
-    match ast.data {
-        syn::Data::Struct(fields) => impl_metadata_kv(ast.ident, fields),
-        _ => panic!("#[derive(IntoMetadataKV)] is only defined for structs"),
-    }
+    metadata::impl_metadata_kv(&ast)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
 }
 
-fn impl_metadata_kv(name: syn::Ident, data_struct: syn::DataStruct) -> TokenStream {
-    let md_fields: Vec<_> = data_struct
-        .fields
-        .iter()
-        .filter_map(|field| field.ident.as_ref())
-        .map(|field_name| {
-            let val = quote! {
-                serde_json::to_string(&self.#field_name).unwrap()
-            };
-            quote! {
-                concourse_resource::internal::KV {
-                    name: String::from(stringify!(#field_name)),
-                    value: #val.strip_prefix('"')
-                    .unwrap_or(&#val)
-                    .strip_suffix('"')
-                    .unwrap_or(&#val)
-                    .to_string()
-                }
-            }
-        })
-        .collect();
+#[proc_macro_derive(ConcourseVersion, attributes(version, serde))]
+pub fn concourse_version_derive(input: TokenStream) -> TokenStream {
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
 
-    let gen = quote! {
-        impl IntoMetadataKV for #name {
-            fn into_metadata_kv(self) -> Vec<concourse_resource::internal::KV> {
-                // let mut md = Vec::new();
-                // md
-                vec![#(#md_fields,)*]
-            }
-        }
-    };
-    gen.into()
+    version::impl_concourse_version(&ast)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
 }
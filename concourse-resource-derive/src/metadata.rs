@@ -0,0 +1,581 @@
+//! `#[derive(IntoMetadataKV)]`, configured with `#[metadata(...)]` attributes
+
+use proc_macro2::{TokenStream, TokenTree};
+use quote::{format_ident, quote, ToTokens};
+use syn::ext::IdentExt;
+
+use crate::{
+    attrs::{self, lit_str, name, options},
+    case::RenameRule,
+};
+
+/// Options of the struct or enum
+#[derive(Default)]
+struct ContainerOptions {
+    rename_all: Option<RenameRule>,
+    tag: Option<syn::LitStr>,
+    serde_tag: Option<String>,
+    krate: Option<syn::LitStr>,
+}
+
+impl ContainerOptions {
+    fn from_attrs(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut options = ContainerOptions::default();
+        for (attr, meta) in serde_options(attrs)? {
+            match (attr.as_str(), &meta) {
+                // `rename_all(serialize = "...")` is not supported, serde's default is used
+                ("rename_all", syn::Meta::NameValue(name_value)) => {
+                    options.rename_all = Some(RenameRule::from_name(&lit_str(&name_value.lit)?)?)
+                }
+                ("tag", syn::Meta::NameValue(name_value)) => {
+                    options.serde_tag = Some(lit_str(&name_value.lit)?.value())
+                }
+                _ => (),
+            }
+        }
+        for meta in metadata_options(attrs)? {
+            match (name(&meta).as_str(), &meta) {
+                ("rename_all", syn::Meta::NameValue(name_value)) => {
+                    options.rename_all = Some(RenameRule::from_name(&lit_str(&name_value.lit)?)?)
+                }
+                ("tag", syn::Meta::NameValue(name_value)) => {
+                    options.tag = Some(lit_str(&name_value.lit)?)
+                }
+                ("crate", syn::Meta::NameValue(name_value)) => {
+                    options.krate = Some(lit_str(&name_value.lit)?)
+                }
+                _ => return Err(unknown_option(&meta, "rename_all, tag, crate")),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Options of a variant of an enum
+#[derive(Default)]
+struct VariantOptions {
+    rename: Option<String>,
+    skip: bool,
+}
+
+impl VariantOptions {
+    fn from_attrs(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut options = VariantOptions::default();
+        for (attr, meta) in serde_options(attrs)? {
+            match (attr.as_str(), &meta) {
+                ("rename", syn::Meta::NameValue(name_value)) => {
+                    options.rename = Some(lit_str(&name_value.lit)?.value())
+                }
+                ("skip", syn::Meta::Path(_)) | ("skip_serializing", syn::Meta::Path(_)) => {
+                    options.skip = true
+                }
+                _ => (),
+            }
+        }
+        for meta in metadata_options(attrs)? {
+            match (name(&meta).as_str(), &meta) {
+                ("rename", syn::Meta::NameValue(name_value)) => {
+                    options.rename = Some(lit_str(&name_value.lit)?.value())
+                }
+                ("skip", syn::Meta::Path(_)) => options.skip = true,
+                _ => return Err(unknown_option(&meta, "rename, skip")),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// How the value of a field is turned into a string
+#[derive(Default)]
+enum Format {
+    /// Serialized, strings unquoted and `None` omitted
+    #[default]
+    Serialize,
+    /// With its `Display` implementation
+    Display,
+    /// With a function taking a reference to the field and returning a `String`
+    With(syn::ExprPath),
+}
+
+impl Format {
+    /// Expression rendering `value`, a reference, as an `Option<String>`, returning early with
+    /// an error naming the entry `name` if it can't be serialized
+    fn render(&self, krate: &syn::Path, name: TokenStream, value: TokenStream) -> TokenStream {
+        match self {
+            Format::Serialize => quote!(#krate::internal::metadata_value(#name, #value)?),
+            Format::Display => {
+                quote!(::std::option::Option::Some(::std::string::ToString::to_string(#value)))
+            }
+            Format::With(with) => quote!(::std::option::Option::Some(#with(#value))),
+        }
+    }
+}
+
+/// How a field is turned into metadata entries
+#[derive(Default)]
+enum Mode {
+    /// One entry for the field
+    #[default]
+    Single,
+    /// The entries of the field, that implements `IntoMetadataKV`, with their names prefixed
+    Flatten,
+    /// One entry per element of the field, named with their index
+    Each,
+    /// One entry with the elements of the field joined with a separator
+    Join(String),
+}
+
+/// Options of a field
+#[derive(Default)]
+struct FieldOptions {
+    rename: Option<String>,
+    skip: bool,
+    skip_if: Option<syn::ExprPath>,
+    format: Format,
+    mode: Mode,
+    prefix: Option<String>,
+}
+
+impl FieldOptions {
+    fn from_field(field: &syn::Field) -> syn::Result<Self> {
+        let attrs = &field.attrs;
+        let mut options = FieldOptions::default();
+        for (attr, meta) in serde_options(attrs)? {
+            match (attr.as_str(), &meta) {
+                ("rename", syn::Meta::NameValue(name_value)) => {
+                    options.rename = Some(lit_str(&name_value.lit)?.value())
+                }
+                ("rename", syn::Meta::List(list)) => {
+                    for nested in &list.nested {
+                        if let syn::NestedMeta::Meta(syn::Meta::NameValue(name_value)) = nested {
+                            if name_value.path.is_ident("serialize") {
+                                options.rename = Some(lit_str(&name_value.lit)?.value());
+                            }
+                        }
+                    }
+                }
+                ("skip", syn::Meta::Path(_)) | ("skip_serializing", syn::Meta::Path(_)) => {
+                    options.skip = true
+                }
+                ("skip_serializing_if", syn::Meta::NameValue(name_value)) => {
+                    options.skip_if = Some(lit_str(&name_value.lit)?.parse()?)
+                }
+                _ => (),
+            }
+        }
+        for meta in metadata_options(attrs)? {
+            match (name(&meta).as_str(), &meta) {
+                ("rename", syn::Meta::NameValue(name_value)) => {
+                    options.rename = Some(lit_str(&name_value.lit)?.value())
+                }
+                ("skip", syn::Meta::Path(_)) => options.skip = true,
+                ("skip_if", syn::Meta::NameValue(name_value)) => {
+                    options.skip_if = Some(lit_str(&name_value.lit)?.parse()?)
+                }
+                ("display", syn::Meta::Path(_)) => options.set_format(&meta, Format::Display)?,
+                ("with", syn::Meta::NameValue(name_value)) => {
+                    options.set_format(&meta, Format::With(lit_str(&name_value.lit)?.parse()?))?
+                }
+                ("flatten", syn::Meta::Path(_)) => options.set_mode(&meta, Mode::Flatten)?,
+                ("prefix", syn::Meta::NameValue(name_value)) => {
+                    options.prefix = Some(lit_str(&name_value.lit)?.value())
+                }
+                ("each", syn::Meta::Path(_)) => options.set_mode(&meta, Mode::Each)?,
+                ("join", syn::Meta::NameValue(name_value)) => {
+                    options.set_mode(&meta, Mode::Join(lit_str(&name_value.lit)?.value()))?
+                }
+                _ => {
+                    return Err(unknown_option(
+                        &meta,
+                        "rename, skip, skip_if, display, with, flatten, prefix, each, join",
+                    ))
+                }
+            }
+        }
+
+        if let Mode::Flatten = options.mode {
+            if !matches!(options.format, Format::Serialize) {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "`flatten` can not be used with `display` or `with`",
+                ));
+            }
+        } else if options.prefix.is_some() {
+            return Err(syn::Error::new_spanned(
+                field,
+                "`prefix` can only be used with `flatten`",
+            ));
+        }
+        Ok(options)
+    }
+
+    fn set_mode(&mut self, meta: &syn::Meta, mode: Mode) -> syn::Result<()> {
+        if let Mode::Single = self.mode {
+            self.mode = mode;
+            Ok(())
+        } else {
+            Err(syn::Error::new_spanned(
+                meta,
+                "only one of `flatten`, `each` and `join` can be used",
+            ))
+        }
+    }
+
+    fn set_format(&mut self, meta: &syn::Meta, format: Format) -> syn::Result<()> {
+        if let Format::Serialize = self.format {
+            self.format = format;
+            Ok(())
+        } else {
+            Err(syn::Error::new_spanned(
+                meta,
+                "only one of `display` and `with` can be used",
+            ))
+        }
+    }
+}
+
+pub(crate) fn impl_metadata_kv(ast: &syn::DeriveInput) -> syn::Result<TokenStream> {
+    let name = &ast.ident;
+    let container = ContainerOptions::from_attrs(&ast.attrs)?;
+    let krate = attrs::crate_path(container.krate.as_ref())?;
+    let mut bounds = Bounds::new(&ast.generics);
+
+    let body = match &ast.data {
+        syn::Data::Struct(data_struct) => {
+            if let Some(tag) = &container.tag {
+                return Err(syn::Error::new_spanned(
+                    tag,
+                    "`tag` can only be used on enums",
+                ));
+            }
+            let entries = fields_entries(
+                &data_struct.fields,
+                container.rename_all,
+                &krate,
+                &mut bounds,
+                |member, _| quote!(self.#member),
+            )?;
+            quote!(#(#entries)*)
+        }
+        syn::Data::Enum(data_enum) => {
+            let tag = container
+                .tag
+                .as_ref()
+                .map(syn::LitStr::value)
+                .or_else(|| container.serde_tag.clone())
+                .unwrap_or_else(|| String::from("variant"));
+            let mut arms = vec![];
+            for variant in &data_enum.variants {
+                let variant_name = &variant.ident;
+                let options = VariantOptions::from_attrs(&variant.attrs)?;
+                let label = options.rename.unwrap_or_else(|| {
+                    let variant_name = variant_name.unraw().to_string();
+                    match container.rename_all {
+                        Some(rule) => rule.apply_to_variant(&variant_name),
+                        None => variant_name,
+                    }
+                });
+                let binding = |index| format_ident!("__metadata_field_{}", index);
+                // like serde, `rename_all` on an enum renames its variants, not their fields
+                let entries =
+                    fields_entries(&variant.fields, None, &krate, &mut bounds, |_, index| {
+                        let binding = binding(index);
+                        quote!(#binding)
+                    })?;
+                let bindings = variant.fields.iter().enumerate().map(|(index, field)| {
+                    let member = member(field, index);
+                    let binding = binding(index);
+                    quote!(#member: #binding)
+                });
+                arms.push(if options.skip {
+                    quote! {
+                        #name::#variant_name { .. } => (),
+                    }
+                } else {
+                    quote! {
+                        #[allow(unused_variables)]
+                        #name::#variant_name { #(#bindings,)* } => {
+                            __metadata.push(#krate::internal::KV {
+                                name: ::std::string::ToString::to_string(#tag),
+                                value: ::std::string::ToString::to_string(#label),
+                            });
+                            #(#entries)*
+                        }
+                    }
+                });
+            }
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        syn::Data::Union(data_union) => {
+            return Err(syn::Error::new_spanned(
+                data_union.union_token,
+                "#[derive(IntoMetadataKV)] is not defined for unions",
+            ))
+        }
+    };
+
+    // a value of an enum without variants can't exist, and any code after matching it would be
+    // unreachable
+    let body = match &ast.data {
+        syn::Data::Enum(data_enum) if data_enum.variants.is_empty() => quote!(match self {}),
+        _ => quote! {
+            let mut __metadata = ::std::vec::Vec::new();
+            #body
+            ::std::result::Result::Ok(__metadata)
+        },
+    };
+
+    let generics = bounds.with_predicates(&ast.generics);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #krate::IntoMetadataKV for #name #ty_generics #where_clause {
+            fn into_metadata_kv(self) -> ::std::vec::Vec<#krate::internal::KV> {
+                #krate::internal::expect_metadata(#krate::IntoMetadataKV::try_into_metadata_kv(self))
+            }
+
+            fn try_into_metadata_kv(
+                self,
+            ) -> ::std::result::Result<
+                ::std::vec::Vec<#krate::internal::KV>,
+                #krate::MetadataError,
+            > {
+                #body
+            }
+        }
+    })
+}
+
+/// Bounds needed by the generated code on the type parameters of the container. Like serde, a
+/// type parameter used by a serialized field must implement `Serialize`, with the same rule for
+/// `Display` on displayed fields. A flattened field must implement `IntoMetadataKV`.
+struct Bounds {
+    params: Vec<syn::Ident>,
+    predicates: Vec<syn::WherePredicate>,
+}
+
+impl Bounds {
+    fn new(generics: &syn::Generics) -> Self {
+        Bounds {
+            params: generics
+                .type_params()
+                .map(|param| param.ident.clone())
+                .collect(),
+            predicates: vec![],
+        }
+    }
+
+    /// Add the bounds needed by a field of type `ty`
+    fn add(&mut self, ty: &syn::Type, options: &FieldOptions, krate: &syn::Path) {
+        let params = self.params_in(ty.to_token_stream());
+        if params.is_empty() {
+            return;
+        }
+        match (&options.mode, &options.format) {
+            (Mode::Flatten, _) => self.push(syn::parse_quote!(#ty: #krate::IntoMetadataKV)),
+            (Mode::Single, Format::Display) => {
+                self.push(syn::parse_quote!(#ty: ::std::fmt::Display))
+            }
+            (_, Format::Display) => {
+                for param in params {
+                    self.push(syn::parse_quote!(#param: ::std::fmt::Display));
+                }
+            }
+            (_, Format::Serialize) => {
+                for param in params {
+                    self.push(syn::parse_quote!(#param: #krate::internal::serde::Serialize));
+                }
+            }
+            (_, Format::With(_)) => (),
+        }
+    }
+
+    fn push(&mut self, predicate: syn::WherePredicate) {
+        let tokens = predicate.to_token_stream().to_string();
+        if !self
+            .predicates
+            .iter()
+            .any(|known| known.to_token_stream().to_string() == tokens)
+        {
+            self.predicates.push(predicate);
+        }
+    }
+
+    /// Type parameters of the container used in `tokens`
+    fn params_in(&self, tokens: TokenStream) -> Vec<syn::Ident> {
+        let mut params = vec![];
+        for token in tokens {
+            match token {
+                TokenTree::Ident(ident) if self.params.contains(&ident) => params.push(ident),
+                TokenTree::Group(group) => params.extend(self.params_in(group.stream())),
+                _ => (),
+            }
+        }
+        params
+    }
+
+    /// `generics` with the bounds added to its where clause
+    fn with_predicates(self, generics: &syn::Generics) -> syn::Generics {
+        let mut generics = generics.clone();
+        generics
+            .make_where_clause()
+            .predicates
+            .extend(self.predicates);
+        generics
+    }
+}
+
+/// Named field, or position of the unnamed field
+fn member(field: &syn::Field, index: usize) -> syn::Member {
+    match &field.ident {
+        Some(ident) => syn::Member::Named(ident.clone()),
+        None => syn::Member::Unnamed(syn::Index::from(index)),
+    }
+}
+
+/// Code pushing the entries of `fields`. `access` gives the expression of the value of a field,
+/// from its member and index.
+fn fields_entries(
+    fields: &syn::Fields,
+    rename_all: Option<RenameRule>,
+    krate: &syn::Path,
+    bounds: &mut Bounds,
+    access: impl Fn(&syn::Member, usize) -> TokenStream,
+) -> syn::Result<Vec<TokenStream>> {
+    let mut entries = vec![];
+    for (index, field) in fields.iter().enumerate() {
+        let options = FieldOptions::from_field(field)?;
+        if options.skip {
+            continue;
+        }
+        bounds.add(&field.ty, &options, krate);
+        let member = member(field, index);
+        let label = options
+            .rename
+            .clone()
+            .unwrap_or_else(|| match &field.ident {
+                Some(field_name) => {
+                    let field_name = field_name.unraw().to_string();
+                    match rename_all {
+                        Some(rule) => rule.apply(&field_name),
+                        None => field_name,
+                    }
+                }
+                None => index.to_string(),
+            });
+        entries.push(field_entries(
+            options,
+            &label,
+            krate,
+            access(&member, index),
+        ));
+    }
+    Ok(entries)
+}
+
+/// Code pushing the entries of a field, whose value is `value`
+fn field_entries(
+    options: FieldOptions,
+    label: &str,
+    krate: &syn::Path,
+    value: TokenStream,
+) -> TokenStream {
+    let push = match options.mode {
+        Mode::Single => {
+            let rendered = options
+                .format
+                .render(krate, quote!(#label), quote!(&#value));
+            quote! {
+                if let ::std::option::Option::Some(__metadata_value) = #rendered {
+                    __metadata.push(#krate::internal::KV {
+                        name: ::std::string::ToString::to_string(#label),
+                        value: __metadata_value,
+                    });
+                }
+            }
+        }
+        Mode::Flatten => {
+            let prefix = options.prefix.unwrap_or_else(|| format!("{}.", label));
+            quote! {
+                let __metadata_entries = #krate::IntoMetadataKV::try_into_metadata_kv(#value)
+                    .map_err(|__metadata_error| {
+                        #krate::internal::flattened_metadata_error(#prefix, __metadata_error)
+                    })?;
+                for __metadata_kv in __metadata_entries {
+                    __metadata.push(#krate::internal::KV {
+                        name: ::std::format!("{}{}", #prefix, __metadata_kv.name),
+                        value: __metadata_kv.value,
+                    });
+                }
+            }
+        }
+        Mode::Each => {
+            let rendered =
+                options
+                    .format
+                    .render(krate, quote!(&__metadata_name), quote!(__metadata_element));
+            quote! {
+                for (__metadata_index, __metadata_element) in ::std::iter::Iterator::enumerate(::std::iter::IntoIterator::into_iter(&#value)) {
+                    let __metadata_name = ::std::format!("{}[{}]", #label, __metadata_index);
+                    if let ::std::option::Option::Some(__metadata_value) = #rendered {
+                        __metadata.push(#krate::internal::KV {
+                            name: __metadata_name,
+                            value: __metadata_value,
+                        });
+                    }
+                }
+            }
+        }
+        Mode::Join(separator) => {
+            let rendered = options
+                .format
+                .render(krate, quote!(#label), quote!(__metadata_element));
+            quote! {
+                let mut __metadata_values = ::std::vec::Vec::new();
+                for __metadata_element in &#value {
+                    if let ::std::option::Option::Some(__metadata_value) = #rendered {
+                        __metadata_values.push(__metadata_value);
+                    }
+                }
+                if !__metadata_values.is_empty() {
+                    __metadata.push(#krate::internal::KV {
+                        name: ::std::string::ToString::to_string(#label),
+                        value: __metadata_values.join(#separator),
+                    });
+                }
+            }
+        }
+    };
+    match options.skip_if {
+        Some(skip_if) => quote! {
+            if !#skip_if(&#value) {
+                #push
+            }
+        },
+        None => quote! {
+            {
+                #push
+            }
+        },
+    }
+}
+
+/// Options in `#[serde(...)]` attributes, with their name
+fn serde_options(attrs: &[syn::Attribute]) -> syn::Result<Vec<(String, syn::Meta)>> {
+    Ok(options(attrs, "serde")?
+        .into_iter()
+        .map(|meta| (name(&meta), meta))
+        .collect())
+}
+
+/// Options in `#[metadata(...)]` attributes
+fn metadata_options(attrs: &[syn::Attribute]) -> syn::Result<Vec<syn::Meta>> {
+    options(attrs, "metadata")
+}
+
+fn unknown_option(meta: &syn::Meta, expected: &str) -> syn::Error {
+    attrs::unknown_option(meta, "metadata", expected)
+}
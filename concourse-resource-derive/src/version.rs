@@ -0,0 +1,200 @@
+//! `#[derive(ConcourseVersion)]`, for versions that are flat objects of string values
+
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::{ext::IdentExt, spanned::Spanned};
+
+use crate::attrs::{self, lit_str, name, options, unknown_option};
+
+/// Collections that can not be serialized as a single string
+const COLLECTIONS: &[&str] = &[
+    "Option",
+    "Vec",
+    "VecDeque",
+    "LinkedList",
+    "HashMap",
+    "BTreeMap",
+    "HashSet",
+    "BTreeSet",
+];
+
+pub(crate) fn impl_concourse_version(ast: &syn::DeriveInput) -> syn::Result<TokenStream> {
+    let name = &ast.ident;
+    if !ast.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &ast.generics,
+            "#[derive(ConcourseVersion)] is not defined for generic structs",
+        ));
+    }
+    let fields = match &ast.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => &fields.named,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "#[derive(ConcourseVersion)] is only defined for structs with named fields",
+            ))
+        }
+    };
+    reject_serde(&ast.attrs)?;
+    let mut idents = vec![];
+    let mut keys = vec![];
+    for field in fields {
+        check_scalar(&field.ty)?;
+        reject_serde(&field.attrs)?;
+        let ident = field.ident.as_ref().expect("named fields have an ident");
+        idents.push(ident);
+        keys.push(match field_rename(&field.attrs)? {
+            Some(rename) => rename,
+            None => ident.unraw().to_string(),
+        });
+    }
+    let krate = crate_path(&ast.attrs)?;
+    let struct_name = name.unraw().to_string();
+    let assertions = fields.iter().map(|field| {
+        let ty = &field.ty;
+        quote_spanned! {ty.span()=>
+            #krate::internal::assert_version_field::<#ty>();
+        }
+    });
+
+    Ok(quote! {
+        const _: fn() = || {
+            #(#assertions)*
+        };
+
+        impl #krate::ConcourseVersion for #name {
+            fn to_version_map(
+                &self,
+            ) -> ::std::collections::BTreeMap<::std::string::String, ::std::string::String> {
+                let mut map = ::std::collections::BTreeMap::new();
+                #(map.insert(
+                    ::std::string::ToString::to_string(#keys),
+                    ::std::string::ToString::to_string(&self.#idents),
+                );)*
+                map
+            }
+
+            fn from_version_map(
+                mut map: ::std::collections::BTreeMap<::std::string::String, ::std::string::String>,
+            ) -> ::std::result::Result<Self, #krate::VersionError> {
+                ::std::result::Result::Ok(#name {
+                    #(#idents: #krate::internal::parse_version_field(&mut map, #keys)?,)*
+                })
+            }
+        }
+
+        impl ::std::convert::From<#name>
+            for ::std::collections::BTreeMap<::std::string::String, ::std::string::String>
+        {
+            fn from(version: #name) -> Self {
+                #krate::ConcourseVersion::to_version_map(&version)
+            }
+        }
+
+        impl ::std::convert::TryFrom<
+            ::std::collections::BTreeMap<::std::string::String, ::std::string::String>,
+        > for #name
+        {
+            type Error = #krate::VersionError;
+
+            fn try_from(
+                map: ::std::collections::BTreeMap<::std::string::String, ::std::string::String>,
+            ) -> ::std::result::Result<Self, Self::Error> {
+                #krate::ConcourseVersion::from_version_map(map)
+            }
+        }
+
+        impl #krate::internal::serde::Serialize for #name {
+            fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+            where
+                S: #krate::internal::serde::Serializer,
+            {
+                #krate::internal::serde::Serialize::serialize(
+                    &#krate::ConcourseVersion::to_version_map(self),
+                    serializer,
+                )
+            }
+        }
+
+        impl<'de> #krate::internal::serde::Deserialize<'de> for #name {
+            fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+            where
+                D: #krate::internal::serde::Deserializer<'de>,
+            {
+                let map = #krate::internal::deserialize_version_map(
+                    deserializer,
+                    #struct_name,
+                    &[#(#keys),*],
+                )?;
+                #krate::ConcourseVersion::from_version_map(map)
+                    .map_err(<D::Error as #krate::internal::serde::de::Error>::custom)
+            }
+        }
+    })
+}
+
+/// Path to the `concourse_resource` crate, from the `#[version(crate = "...")]` option
+fn crate_path(attrs: &[syn::Attribute]) -> syn::Result<syn::Path> {
+    let mut krate = None;
+    for meta in options(attrs, "version")? {
+        match (name(&meta).as_str(), &meta) {
+            ("crate", syn::Meta::NameValue(name_value)) => krate = Some(lit_str(&name_value.lit)?),
+            _ => return Err(unknown_option(&meta, "version", "crate")),
+        }
+    }
+    attrs::crate_path(krate.as_ref())
+}
+
+/// Key of a field, from the `#[version(rename = "...")]` option
+fn field_rename(attrs: &[syn::Attribute]) -> syn::Result<Option<String>> {
+    let mut rename = None;
+    for meta in options(attrs, "version")? {
+        match (name(&meta).as_str(), &meta) {
+            ("rename", syn::Meta::NameValue(name_value)) => {
+                rename = Some(lit_str(&name_value.lit)?.value())
+            }
+            _ => return Err(unknown_option(&meta, "version", "rename")),
+        }
+    }
+    Ok(rename)
+}
+
+/// The derive implements `Serialize` and `Deserialize` itself, `#[serde(...)]` attributes would
+/// be silently ignored
+fn reject_serde(attrs: &[syn::Attribute]) -> syn::Result<()> {
+    match attrs.iter().find(|attr| attr.path.is_ident("serde")) {
+        Some(attr) => Err(syn::Error::new_spanned(
+            attr,
+            "#[serde(...)] is not supported by #[derive(ConcourseVersion)], which implements `Serialize` and `Deserialize`, use #[version(rename = \"...\")] to rename a field",
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Reject types that obviously can't be a single string. Other types are checked to implement
+/// `Display` and `FromStr` by the generated code.
+fn check_scalar(ty: &syn::Type) -> syn::Result<()> {
+    match ty {
+        syn::Type::Group(group) => check_scalar(&group.elem),
+        syn::Type::Paren(paren) => check_scalar(&paren.elem),
+        syn::Type::Path(path) => match path.path.segments.last() {
+            Some(segment) if COLLECTIONS.iter().any(|collection| segment.ident == collection) => {
+                Err(syn::Error::new_spanned(
+                    ty,
+                    format!(
+                        "`{}` can not be a version field, Concourse versions are flat objects of string values",
+                        segment.ident
+                    ),
+                ))
+            }
+            _ => Ok(()),
+        },
+        _ => Err(syn::Error::new_spanned(
+            ty,
+            "version fields must be scalars, Concourse versions are flat objects of string values",
+        )),
+    }
+}
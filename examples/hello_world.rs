@@ -1,4 +1,4 @@
-use std::{fmt, fs::File, io::Write, path::Path};
+use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
@@ -23,9 +23,10 @@ struct InParams {
     action: Action,
 }
 
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Deserialize, Clone, Copy, Default)]
 #[serde(rename_all = "lowercase")]
 enum Action {
+    #[default]
     Hello,
     Goodbye,
 }
@@ -39,18 +40,12 @@ impl fmt::Display for Action {
     }
 }
 
-impl Default for Action {
-    fn default() -> Self {
-        Action::Hello
-    }
-}
-
 #[derive(Serialize, Debug, IntoMetadataKV)]
 struct InMetadata {
     said: String,
 }
 
-impl Resource for HelloWorld {
+impl TryResource for HelloWorld {
     type Version = Version;
 
     type Source = Source;
@@ -61,21 +56,22 @@ impl Resource for HelloWorld {
     type OutParams = concourse_resource::Empty;
     type OutMetadata = concourse_resource::Empty;
 
-    fn resource_check(
+    fn try_resource_check(
         _source: Option<Self::Source>,
         _version: Option<Self::Version>,
-    ) -> Vec<Self::Version> {
-        vec![Self::Version {
+        _context: &CheckContext,
+    ) -> Result<Vec<Self::Version>, Error> {
+        Ok(vec![Self::Version {
             ver: String::from("static"),
-        }]
+        }])
     }
 
-    fn resource_in(
+    fn try_resource_in(
         source: Option<Self::Source>,
         _version: Self::Version,
         params: Option<Self::InParams>,
-        output_path: &str,
-    ) -> Result<InOutput<Self::Version, Self::InMetadata>, Box<dyn std::error::Error>> {
+        context: &InContext,
+    ) -> Result<InOutput<Self::Version, Self::InMetadata>, Error> {
         let action = params
             .as_ref()
             .map(|p| p.action)
@@ -86,11 +82,11 @@ impl Resource for HelloWorld {
             .unwrap_or_else(|| String::from("world"));
 
         let hello_world = format!("{}, {}!", action, name);
+        context.logger().info(&hello_world);
 
-        let mut path = Path::new(output_path).to_path_buf();
-        path.push("hello_world.txt");
-        let mut file = File::create(path)?;
-        file.write_all(hello_world.as_bytes())?;
+        context
+            .output_dir()
+            .write_text("hello_world.txt", &hello_world)?;
 
         Ok(InOutput {
             version: Self::Version {
@@ -100,17 +96,17 @@ impl Resource for HelloWorld {
         })
     }
 
-    fn resource_out(
+    fn try_resource_out(
         _source: Option<Self::Source>,
         _params: Option<Self::OutParams>,
-        _input_path: &str,
-    ) -> OutOutput<Self::Version, Self::OutMetadata> {
-        OutOutput {
+        _context: &OutContext,
+    ) -> Result<OutOutput<Self::Version, Self::OutMetadata>, Error> {
+        Ok(OutOutput {
             version: Self::Version {
                 ver: String::from("static"),
             },
             metadata: None,
-        }
+        })
     }
 }
 
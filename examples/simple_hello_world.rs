@@ -1,4 +1,4 @@
-use std::{fs::File, io::Write, path::Path};
+use std::{fs::File, io::Write};
 
 use serde::{Deserialize, Serialize};
 
@@ -11,7 +11,7 @@ struct Version {
     ver: String,
 }
 
-impl Resource for HelloWorld {
+impl TryResource for HelloWorld {
     type Version = Version;
 
     type Source = concourse_resource::Empty;
@@ -22,21 +22,23 @@ impl Resource for HelloWorld {
     type OutParams = concourse_resource::Empty;
     type OutMetadata = concourse_resource::Empty;
 
-    fn resource_check(_: Option<Self::Source>, _: Option<Self::Version>) -> Vec<Self::Version> {
-        vec![Self::Version {
+    fn try_resource_check(
+        _: Option<Self::Source>,
+        _: Option<Self::Version>,
+        _: &CheckContext,
+    ) -> Result<Vec<Self::Version>, Error> {
+        Ok(vec![Self::Version {
             ver: String::from("static"),
-        }]
+        }])
     }
 
-    fn resource_in(
+    fn try_resource_in(
         _source: Option<Self::Source>,
         _version: Self::Version,
         _params: Option<Self::InParams>,
-        output_path: &str,
-    ) -> Result<InOutput<Self::Version, Self::InMetadata>, Box<dyn std::error::Error>> {
-        let mut path = Path::new(output_path).to_path_buf();
-        path.push("hello_world.txt");
-        let mut file = File::create(path)?;
+        context: &InContext,
+    ) -> Result<InOutput<Self::Version, Self::InMetadata>, Error> {
+        let mut file = File::create(context.output_path().join("hello_world.txt"))?;
         file.write_all(b"hello, world!")?;
 
         Ok(InOutput {
@@ -47,12 +49,12 @@ impl Resource for HelloWorld {
         })
     }
 
-    fn resource_out(
+    fn try_resource_out(
         _: Option<Self::Source>,
         _: Option<Self::OutParams>,
-        _: &str,
-    ) -> OutOutput<Self::Version, Self::OutMetadata> {
-        unimplemented!()
+        _: &OutContext,
+    ) -> Result<OutOutput<Self::Version, Self::OutMetadata>, Error> {
+        Err("this resource does not support put".into())
     }
 }
 
@@ -0,0 +1,319 @@
+//! Metadata about the running build
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::dispatch;
+
+/// When used in a "get" or "put" step, metadata about the running build is made available
+/// via environment variables.
+///
+/// If the build is a one-off, `name`, `job_name`, `pipeline_name`, and `pipeline_instance_vars`
+/// will be `None`. `pipeline_instance_vars` will also be `None` if the build's pipeline is not a
+/// pipeline instance (i.e. it is a regular pipeline).
+///
+/// [Concourse documentation](https://concourse-ci.org/implementing-resource-types.html#resource-metadata)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildMetadata {
+    /// The internal identifier for the build. Right now this is numeric but it may become
+    /// a guid in the future. Treat it as an absolute reference to the build.
+    pub id: String,
+    /// The build number within the build's job.
+    pub name: Option<String>,
+    /// The name of the build's job.
+    pub job_name: Option<String>,
+    /// The pipeline that the build's job lives in.
+    pub pipeline_name: Option<String>,
+    /// The pipeline's instance vars, used to differentiate pipeline instances.
+    pub pipeline_instance_vars: Option<Map<String, Value>>,
+    /// The team that the build belongs to.
+    pub team_name: String,
+    /// The public URL for your ATC; useful for debugging.
+    pub atc_external_url: String,
+}
+
+/// Error when reading [`BuildMetadata`](struct.BuildMetadata.html) from the environment
+#[derive(Debug)]
+pub enum BuildMetadataError {
+    /// A required environment variable is not set. Concourse only sets them for "get" and "put"
+    /// steps, not for "check".
+    MissingVariable(&'static str),
+    /// `BUILD_PIPELINE_INSTANCE_VARS` is not a JSON object
+    InvalidInstanceVars(serde_json::Error),
+}
+
+impl fmt::Display for BuildMetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildMetadataError::MissingVariable(name) => {
+                write!(f, "environment variable {} should be present", name)
+            }
+            BuildMetadataError::InvalidInstanceVars(_) => write!(
+                f,
+                "environment variable BUILD_PIPELINE_INSTANCE_VARS should be a JSON object"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BuildMetadataError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BuildMetadataError::MissingVariable(_) => None,
+            BuildMetadataError::InvalidInstanceVars(error) => Some(error),
+        }
+    }
+}
+
+impl BuildMetadata {
+    /// Read the metadata of the running build from the environment given to
+    /// [`run`](fn.run.html) when called during a step, or from the process environment otherwise
+    pub fn from_env() -> Result<BuildMetadata, BuildMetadataError> {
+        BuildMetadata::from_lookup(dispatch::env_var)
+    }
+
+    /// Read the metadata of the running build from a map of environment variables
+    ///
+    /// ```
+    /// # use concourse_resource::BuildMetadata;
+    /// let metadata = BuildMetadata::from_map(vec![
+    ///     ("BUILD_ID", "42"),
+    ///     ("BUILD_TEAM_NAME", "main"),
+    ///     ("ATC_EXTERNAL_URL", "https://ci.example.com"),
+    /// ])
+    /// .unwrap();
+    /// assert_eq!(metadata.id, "42");
+    /// assert_eq!(metadata.job_name, None);
+    /// ```
+    pub fn from_map(
+        env: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+    ) -> Result<BuildMetadata, BuildMetadataError> {
+        let env: BTreeMap<String, String> = env
+            .into_iter()
+            .map(|(name, value)| (name.into(), value.into()))
+            .collect();
+        BuildMetadata::from_lookup(|name| env.get(name).cloned())
+    }
+
+    pub(crate) fn from_lookup(
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<BuildMetadata, BuildMetadataError> {
+        let required = |name| var(name).ok_or(BuildMetadataError::MissingVariable(name));
+        Ok(BuildMetadata {
+            id: required("BUILD_ID")?,
+            name: var("BUILD_NAME"),
+            job_name: var("BUILD_JOB_NAME"),
+            pipeline_name: var("BUILD_PIPELINE_NAME"),
+            pipeline_instance_vars: var("BUILD_PIPELINE_INSTANCE_VARS")
+                .map(|instance_vars| serde_json::from_str(&instance_vars))
+                .transpose()
+                .map_err(BuildMetadataError::InvalidInstanceVars)?,
+            team_name: required("BUILD_TEAM_NAME")?,
+            atc_external_url: required("ATC_EXTERNAL_URL")?,
+        })
+    }
+
+    /// URL of the running build in the web UI. One-off builds are reached through their id.
+    ///
+    /// ```
+    /// # use concourse_resource::BuildMetadata;
+    /// let metadata = BuildMetadata::builder()
+    ///     .atc_external_url("https://ci.example.com")
+    ///     .pipeline_name("release")
+    ///     .pipeline_instance_var("branch", "feature/x")
+    ///     .job_name("publish")
+    ///     .name("12")
+    ///     .build();
+    /// assert_eq!(
+    ///     metadata.build_url(),
+    ///     "https://ci.example.com/teams/main/pipelines/release/jobs/publish/builds/12?vars.branch=%22feature%2Fx%22"
+    /// );
+    /// ```
+    pub fn build_url(&self) -> String {
+        match (self.job_url_without_vars(), &self.name) {
+            (Some(job_url), Some(name)) => format!(
+                "{}/builds/{}{}",
+                job_url,
+                encode(name),
+                self.instance_vars_query()
+            ),
+            _ => format!("{}/builds/{}", self.atc_url(), encode(&self.id)),
+        }
+    }
+
+    /// URL of the job of the running build in the web UI, `None` for a one-off build
+    pub fn job_url(&self) -> Option<String> {
+        self.job_url_without_vars()
+            .map(|job_url| format!("{}{}", job_url, self.instance_vars_query()))
+    }
+
+    /// URL of the pipeline of the running build in the web UI, `None` for a one-off build
+    pub fn pipeline_url(&self) -> Option<String> {
+        self.pipeline_url_without_vars()
+            .map(|pipeline_url| format!("{}{}", pipeline_url, self.instance_vars_query()))
+    }
+
+    /// URL of the dashboard of the team of the running build in the web UI
+    pub fn team_url(&self) -> String {
+        format!(
+            "{}/?search={}",
+            self.atc_url(),
+            encode(&format!("team: {}", self.team_name))
+        )
+    }
+
+    fn atc_url(&self) -> &str {
+        self.atc_external_url.trim_end_matches('/')
+    }
+
+    fn pipeline_url_without_vars(&self) -> Option<String> {
+        self.pipeline_name.as_ref().map(|pipeline_name| {
+            format!(
+                "{}/teams/{}/pipelines/{}",
+                self.atc_url(),
+                encode(&self.team_name),
+                encode(pipeline_name)
+            )
+        })
+    }
+
+    fn job_url_without_vars(&self) -> Option<String> {
+        match (self.pipeline_url_without_vars(), &self.job_name) {
+            (Some(pipeline_url), Some(job_name)) => {
+                Some(format!("{}/jobs/{}", pipeline_url, encode(job_name)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Instance vars as a query string, each var as `vars.<name>=<JSON value>`, with nested
+    /// objects flattened into dotted names
+    fn instance_vars_query(&self) -> String {
+        fn flatten(prefix: &str, vars: &Map<String, Value>, query: &mut Vec<String>) {
+            for (name, value) in vars {
+                let name = format!("{}.{}", prefix, name);
+                match value {
+                    Value::Object(vars) => flatten(&name, vars, query),
+                    value => {
+                        query.push(format!("{}={}", encode(&name), encode(&value.to_string())))
+                    }
+                }
+            }
+        }
+
+        let mut query = vec![];
+        if let Some(vars) = &self.pipeline_instance_vars {
+            flatten("vars", vars, &mut query);
+        }
+        if query.is_empty() {
+            String::new()
+        } else {
+            format!("?{}", query.join("&"))
+        }
+    }
+
+    /// Builder for metadata of a fake build, for tests. It starts as the one-off build `1` of
+    /// team `main` on `http://localhost:8080`.
+    ///
+    /// ```
+    /// # use concourse_resource::BuildMetadata;
+    /// let metadata = BuildMetadata::builder()
+    ///     .pipeline_name("release")
+    ///     .job_name("publish")
+    ///     .name("12")
+    ///     .build();
+    /// assert_eq!(metadata.team_name, "main");
+    /// ```
+    pub fn builder() -> BuildMetadataBuilder {
+        BuildMetadataBuilder {
+            metadata: BuildMetadata {
+                id: String::from("1"),
+                name: None,
+                job_name: None,
+                pipeline_name: None,
+                pipeline_instance_vars: None,
+                team_name: String::from("main"),
+                atc_external_url: String::from("http://localhost:8080"),
+            },
+        }
+    }
+}
+
+/// Builder for [`BuildMetadata`](struct.BuildMetadata.html), created with
+/// [`BuildMetadata::builder`](struct.BuildMetadata.html#method.builder)
+#[derive(Debug, Clone)]
+pub struct BuildMetadataBuilder {
+    metadata: BuildMetadata,
+}
+
+impl BuildMetadataBuilder {
+    /// Set the internal identifier for the build
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.metadata.id = id.into();
+        self
+    }
+
+    /// Set the build number within the build's job
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.metadata.name = Some(name.into());
+        self
+    }
+
+    /// Set the name of the build's job
+    pub fn job_name(mut self, job_name: impl Into<String>) -> Self {
+        self.metadata.job_name = Some(job_name.into());
+        self
+    }
+
+    /// Set the pipeline that the build's job lives in
+    pub fn pipeline_name(mut self, pipeline_name: impl Into<String>) -> Self {
+        self.metadata.pipeline_name = Some(pipeline_name.into());
+        self
+    }
+
+    /// Add an instance var to the build's pipeline
+    pub fn pipeline_instance_var(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> Self {
+        self.metadata
+            .pipeline_instance_vars
+            .get_or_insert_with(Map::new)
+            .insert(name.into(), value.into());
+        self
+    }
+
+    /// Set the team that the build belongs to
+    pub fn team_name(mut self, team_name: impl Into<String>) -> Self {
+        self.metadata.team_name = team_name.into();
+        self
+    }
+
+    /// Set the public URL of the ATC
+    pub fn atc_external_url(mut self, atc_external_url: impl Into<String>) -> Self {
+        self.metadata.atc_external_url = atc_external_url.into();
+        self
+    }
+
+    /// Build the metadata
+    pub fn build(self) -> BuildMetadata {
+        self.metadata
+    }
+}
+
+/// Percent-encode everything but unreserved characters, so that `value` can be used as a path
+/// segment or in a query string
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
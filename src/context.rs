@@ -0,0 +1,257 @@
+//! Context of the step being run, given to the methods of a `TryResource`
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::{BuildMetadata, BuildMetadataError, InputDir, OutputDir};
+
+/// Logger writing to the build log. Concourse shows the stderr of a resource in the build log,
+/// while stdout is reserved for the response of the resource.
+///
+/// It can be shared between threads, each message being written at once.
+pub struct Logger<'a> {
+    output: Mutex<Box<dyn Write + Send + 'a>>,
+}
+
+impl<'a> Logger<'a> {
+    /// Create a logger writing to `output`
+    pub fn new(output: impl Write + Send + 'a) -> Self {
+        Logger {
+            output: Mutex::new(Box::new(output)),
+        }
+    }
+
+    /// Log a message
+    pub fn info(&self, message: impl fmt::Display) {
+        let _ = writeln!(self.output(), "{}", message);
+    }
+
+    /// Log a warning
+    pub fn warn(&self, message: impl fmt::Display) {
+        let _ = writeln!(self.output(), "warning: {}", message);
+    }
+
+    /// The output, even if a thread panicked while logging
+    fn output(&self) -> MutexGuard<'_, Box<dyn Write + Send + 'a>> {
+        self.output.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for Logger<'static> {
+    fn default() -> Self {
+        Logger::new(std::io::stderr())
+    }
+}
+
+impl fmt::Debug for Logger<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Logger").finish()
+    }
+}
+
+#[derive(Debug)]
+struct StepContext<'a> {
+    env: BTreeMap<String, String>,
+    logger: Logger<'a>,
+    deadline: Option<Instant>,
+}
+
+impl StepContext<'static> {
+    fn new() -> Self {
+        StepContext {
+            env: BTreeMap::new(),
+            logger: Logger::default(),
+            deadline: None,
+        }
+    }
+}
+
+impl<'a> StepContext<'a> {
+    fn with_logger<'b>(self, logger: Logger<'b>) -> StepContext<'b> {
+        StepContext {
+            env: self.env,
+            logger,
+            deadline: self.deadline,
+        }
+    }
+}
+
+macro_rules! step_context_methods {
+    () => {
+        /// Replace the environment variables visible to the step
+        pub fn with_env(
+            mut self,
+            env: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+        ) -> Self {
+            self.step.env = env
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect();
+            self
+        }
+
+        /// Set the deadline of the step
+        pub fn with_deadline(mut self, deadline: Instant) -> Self {
+            self.step.deadline = Some(deadline);
+            self
+        }
+
+        /// Environment variables visible to the step
+        pub fn env(&self) -> &BTreeMap<String, String> {
+            &self.step.env
+        }
+
+        /// Value of the environment variable `name`
+        pub fn var(&self, name: &str) -> Option<&str> {
+            self.step.env.get(name).map(String::as_str)
+        }
+
+        /// Logger writing to the build log
+        pub fn logger(&self) -> &Logger<'a> {
+            &self.step.logger
+        }
+
+        /// Instant by which the step should be done, if any. It is not enforced, but can be used
+        /// to bound waits and retries.
+        pub fn deadline(&self) -> Option<Instant> {
+            self.step.deadline
+        }
+
+        /// Time left before the deadline, if any
+        pub fn remaining(&self) -> Option<Duration> {
+            self.step
+                .deadline
+                .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+        }
+    };
+}
+
+/// Context of a "check" step
+#[derive(Debug)]
+pub struct CheckContext<'a> {
+    step: StepContext<'a>,
+}
+
+impl CheckContext<'static> {
+    /// Create a context with an empty environment, logging to stderr and without deadline
+    pub fn new() -> Self {
+        CheckContext {
+            step: StepContext::new(),
+        }
+    }
+}
+
+impl Default for CheckContext<'static> {
+    fn default() -> Self {
+        CheckContext::new()
+    }
+}
+
+impl<'a> CheckContext<'a> {
+    step_context_methods!();
+
+    /// Replace the logger of the step
+    pub fn with_logger<'b>(self, logger: Logger<'b>) -> CheckContext<'b> {
+        CheckContext {
+            step: self.step.with_logger(logger),
+        }
+    }
+}
+
+/// Context of an "in" step
+#[derive(Debug)]
+pub struct InContext<'a> {
+    step: StepContext<'a>,
+    output_dir: OutputDir,
+}
+
+impl InContext<'static> {
+    /// Create a context fetching into `output_path`, with an empty environment, logging to stderr
+    /// and without deadline
+    pub fn new(output_path: impl Into<PathBuf>) -> Self {
+        InContext {
+            step: StepContext::new(),
+            output_dir: OutputDir::new(output_path),
+        }
+    }
+}
+
+impl<'a> InContext<'a> {
+    step_context_methods!();
+
+    /// Replace the logger of the step
+    pub fn with_logger<'b>(self, logger: Logger<'b>) -> InContext<'b> {
+        InContext {
+            step: self.step.with_logger(logger),
+            output_dir: self.output_dir,
+        }
+    }
+
+    /// Destination directory, where the resource must be fetched
+    pub fn output_dir(&self) -> &OutputDir {
+        &self.output_dir
+    }
+
+    /// Path of the [destination directory](#method.output_dir)
+    pub fn output_path(&self) -> &Path {
+        self.output_dir.path()
+    }
+
+    /// [Metadata](struct.BuildMetadata.html) about the running build, read from the environment
+    /// of the step.
+    pub fn build_metadata(&self) -> Result<BuildMetadata, BuildMetadataError> {
+        BuildMetadata::from_lookup(|name| self.var(name).map(String::from))
+    }
+}
+
+/// Context of an "out" step
+#[derive(Debug)]
+pub struct OutContext<'a> {
+    step: StepContext<'a>,
+    input_dir: InputDir,
+}
+
+impl OutContext<'static> {
+    /// Create a context with the build's sources in `input_path`, with an empty environment,
+    /// logging to stderr and without deadline
+    pub fn new(input_path: impl Into<PathBuf>) -> Self {
+        OutContext {
+            step: StepContext::new(),
+            input_dir: InputDir::new(input_path),
+        }
+    }
+}
+
+impl<'a> OutContext<'a> {
+    step_context_methods!();
+
+    /// Replace the logger of the step
+    pub fn with_logger<'b>(self, logger: Logger<'b>) -> OutContext<'b> {
+        OutContext {
+            step: self.step.with_logger(logger),
+            input_dir: self.input_dir,
+        }
+    }
+
+    /// Directory containing the build's full set of sources
+    pub fn input_dir(&self) -> &InputDir {
+        &self.input_dir
+    }
+
+    /// Path of the [directory containing the build's sources](#method.input_dir)
+    pub fn input_path(&self) -> &Path {
+        self.input_dir.path()
+    }
+
+    /// [Metadata](struct.BuildMetadata.html) about the running build, read from the environment
+    /// of the step.
+    pub fn build_metadata(&self) -> Result<BuildMetadata, BuildMetadataError> {
+        BuildMetadata::from_lookup(|name| self.var(name).map(String::from))
+    }
+}
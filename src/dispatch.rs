@@ -0,0 +1,523 @@
+//! Dispatch of a process invocation to the step methods of a `TryResource`
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    fmt,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Instant,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{
+    install,
+    internal::{CheckInput, InInput, InOutputKV, OutInput, OutOutputKV, KV},
+    persist, renamed_key, unknown_keys,
+    validate::{self, InvalidResponse},
+    CheckContext, Error, InContext, InOutput, IntoMetadataKV, Logger, OutContext, OutOutput,
+    OutputDir, RenamedKey, TryResource, UnknownKeys,
+};
+
+thread_local! {
+    static ENV: RefCell<Option<BTreeMap<String, String>>> = const { RefCell::new(None) };
+}
+
+/// Read an environment variable, from the environment given to [`run`](fn.run.html) if called
+/// while it is running, or from the process environment otherwise.
+pub(crate) fn env_var(name: &str) -> Option<String> {
+    ENV.with(|env| match env.borrow().as_ref() {
+        Some(env) => env.get(name).cloned(),
+        None => std::env::var(name).ok(),
+    })
+}
+
+/// Run the resource `R` as Concourse would: the step to run is selected from `args` (the first one
+/// being the path the binary was called as), the JSON payload is read from `stdin`, and the JSON
+/// response is written to `stdout`. Errors are written to `stderr`.
+///
+/// `env` is the environment visible to the resource while the step is running, in place of the
+/// process environment. Variables whose name or value is not valid UTF-8 are left out, as if they
+/// were not set.
+///
+/// This is what the `main` function built by [`create_resource!`](macro.create_resource.html)
+/// calls, and can be used to test a resource end to end:
+///
+/// ```
+/// # use concourse_resource::*;
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Serialize, Deserialize)]
+/// # struct Version { ver: String }
+/// # struct HelloWorld;
+/// # impl TryResource for HelloWorld {
+/// #     type Version = Version;
+/// #     type Source = Empty;
+/// #     type InParams = Empty;
+/// #     type InMetadata = Empty;
+/// #     type OutParams = Empty;
+/// #     type OutMetadata = Empty;
+/// #     fn try_resource_check(
+/// #         _: Option<Empty>,
+/// #         _: Option<Version>,
+/// #         _: &CheckContext,
+/// #     ) -> Result<Vec<Version>, Error> {
+/// #         Ok(vec![Version { ver: String::from("static") }])
+/// #     }
+/// #     fn try_resource_in(
+/// #         _: Option<Empty>,
+/// #         _: Version,
+/// #         _: Option<Empty>,
+/// #         _: &InContext,
+/// #     ) -> Result<InOutput<Version, Empty>, Error> {
+/// #         Err("get is not supported".into())
+/// #     }
+/// #     fn try_resource_out(
+/// #         _: Option<Empty>,
+/// #         _: Option<Empty>,
+/// #         _: &OutContext,
+/// #     ) -> Result<OutOutput<Version, Empty>, Error> {
+/// #         Err("put is not supported".into())
+/// #     }
+/// # }
+/// let mut stdout = vec![];
+/// let exit_code = run::<HelloWorld>(
+///     vec!["/opt/resource/check"],
+///     r#"{}"#.as_bytes(),
+///     &mut stdout,
+///     std::io::sink(),
+///     vec![("BUILD_ID", "1")],
+/// );
+///
+/// assert_eq!(exit_code, std::process::ExitCode::SUCCESS);
+/// assert_eq!(String::from_utf8(stdout).unwrap(), "[{\"ver\":\"static\"}]\n");
+/// ```
+pub fn run<R: TryResource>(
+    args: impl IntoIterator<Item = impl Into<OsString>>,
+    stdin: impl Read,
+    mut stdout: impl Write,
+    mut stderr: impl Write + Send,
+    env: impl IntoIterator<Item = (impl Into<OsString>, impl Into<OsString>)>,
+) -> ExitCode {
+    let started = Instant::now();
+    let env: BTreeMap<String, String> = env
+        .into_iter()
+        .filter_map(|(name, value)| {
+            Some((
+                name.into().into_string().ok()?,
+                value.into().into_string().ok()?,
+            ))
+        })
+        .collect();
+    let env_override = EnvOverride::set(env.clone());
+
+    let invocation = Invocation {
+        env,
+        deadline: R::STEP_TIMEOUT.map(|timeout| started + timeout),
+    };
+    let result = dispatch::<R>(
+        args.into_iter().map(Into::into).collect(),
+        stdin,
+        &mut stdout,
+        &mut stderr,
+        invocation,
+    );
+
+    drop(env_override);
+
+    match result {
+        Ok(exit_code) => exit_code,
+        Err(error) => {
+            let _ = writeln!(stderr, "error: {:#}", error);
+            ExitCode::from(&error)
+        }
+    }
+}
+
+/// Override of the environment read by `env_var` while `run` is running. The previous one is
+/// restored when this is dropped, including when the step panics.
+struct EnvOverride {
+    previous: Option<BTreeMap<String, String>>,
+}
+
+impl EnvOverride {
+    fn set(env: BTreeMap<String, String>) -> Self {
+        EnvOverride {
+            previous: ENV.with(|current| current.replace(Some(env))),
+        }
+    }
+}
+
+impl Drop for EnvOverride {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        let _ = ENV.try_with(|current| current.replace(previous));
+    }
+}
+
+/// Environment of the process running the resource
+struct Invocation {
+    env: BTreeMap<String, String>,
+    deadline: Option<Instant>,
+}
+
+impl Invocation {
+    fn check_context<'a>(&self, stderr: &'a mut (impl Write + Send)) -> CheckContext<'a> {
+        let context = CheckContext::new().with_env(self.env.clone());
+        let context = match self.deadline {
+            Some(deadline) => context.with_deadline(deadline),
+            None => context,
+        };
+        context.with_logger(Logger::new(stderr))
+    }
+
+    fn in_context<'a>(
+        &self,
+        output_path: PathBuf,
+        stderr: &'a mut (impl Write + Send),
+    ) -> InContext<'a> {
+        let context = InContext::new(output_path).with_env(self.env.clone());
+        let context = match self.deadline {
+            Some(deadline) => context.with_deadline(deadline),
+            None => context,
+        };
+        context.with_logger(Logger::new(stderr))
+    }
+
+    fn out_context<'a>(
+        &self,
+        input_path: PathBuf,
+        stderr: &'a mut (impl Write + Send),
+    ) -> OutContext<'a> {
+        let context = OutContext::new(input_path).with_env(self.env.clone());
+        let context = match self.deadline {
+            Some(deadline) => context.with_deadline(deadline),
+            None => context,
+        };
+        context.with_logger(Logger::new(stderr))
+    }
+}
+
+/// Step of a resource, as called by Concourse
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    Check,
+    In,
+    Out,
+}
+
+impl Step {
+    fn from_name(name: &OsStr) -> Option<Step> {
+        match name.to_str()? {
+            "check" => Some(Step::Check),
+            "in" => Some(Step::In),
+            "out" => Some(Step::Out),
+            _ => None,
+        }
+    }
+}
+
+fn dispatch<R: TryResource>(
+    args: Vec<OsString>,
+    stdin: impl Read,
+    stdout: &mut impl Write,
+    stderr: &mut (impl Write + Send),
+    invocation: Invocation,
+) -> Result<ExitCode, Error> {
+    let mut args = args.into_iter();
+    let bin_name = args
+        .next()
+        .ok_or_else(|| Error::internal("should have a bin name"))?;
+
+    // Concourse calls the resource through `<prefix>/check`, `<prefix>/in` and `<prefix>/out`,
+    // otherwise the step can be given as a subcommand
+    let step = match Path::new(&bin_name).file_name().and_then(Step::from_name) {
+        Some(step) => Some(step),
+        None => match args.next() {
+            Some(command) if command == "install" => {
+                let dir = args
+                    .next()
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| PathBuf::from(R::INSTALL_PREFIX));
+                std::env::current_exe()
+                    .and_then(|exe| install::install(&exe, &dir, stderr))
+                    .map_err(Error::internal)?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            command => command.as_deref().and_then(Step::from_name),
+        },
+    };
+    match (step, args.next().map(PathBuf::from)) {
+        (Some(Step::Check), _) => {
+            let context = invocation.check_context(stderr);
+            check::<R>(&read_input(stdin)?, stdout, context)?
+        }
+        (Some(Step::In), Some(path)) => {
+            let context = invocation.in_context(path, stderr);
+            resource_in::<R>(&read_input(stdin)?, stdout, context)?
+        }
+        (Some(Step::Out), Some(path)) => {
+            let context = invocation.out_context(path, stderr);
+            resource_out::<R>(&read_input(stdin)?, stdout, context)?
+        }
+        _ => {
+            write_usage::<R>(&bin_name, stderr).map_err(Error::internal)?;
+            return Ok(ExitCode::from(USAGE_EXIT_CODE));
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn read_input(mut stdin: impl Read) -> Result<String, Error> {
+    let mut input = String::new();
+    stdin.read_to_string(&mut input).map_err(Error::internal)?;
+    Ok(input)
+}
+
+/// Exit code when the binary is called with unexpected arguments
+const USAGE_EXIT_CODE: u8 = 2;
+
+fn write_usage<R: TryResource>(bin_name: &OsStr, stderr: &mut impl Write) -> io::Result<()> {
+    let bin_name = bin_name.to_string_lossy();
+    let prefix = Path::new(R::INSTALL_PREFIX);
+    writeln!(stderr, "unexpected being called as '{}'", bin_name)?;
+    writeln!(stderr)?;
+    writeln!(stderr, "usage: {} check", bin_name)?;
+    writeln!(stderr, "       {} in <destination directory>", bin_name)?;
+    writeln!(stderr, "       {} out <sources directory>", bin_name)?;
+    writeln!(
+        stderr,
+        "       {} install [directory, default {}]",
+        bin_name,
+        prefix.display()
+    )?;
+    writeln!(stderr)?;
+    writeln!(
+        stderr,
+        "Concourse calls the resource as {}, {} and {}, with its input as JSON on stdin.",
+        prefix.join("check").display(),
+        prefix.join("in").display(),
+        prefix.join("out").display(),
+    )?;
+    Ok(())
+}
+
+fn check<R: TryResource>(
+    input: &str,
+    stdout: &mut impl Write,
+    context: CheckContext,
+) -> Result<(), Error> {
+    let input: CheckInput<Value, Value> = parse_input(input)?;
+    let source = parse_section::<R, _>(input.source, Section::Source, context.logger())?;
+    let version = parse_section::<R, _>(input.version, Section::Version, context.logger())?;
+
+    let versions = R::try_resource_check(source, version, &context)?;
+
+    respond(stdout, &versions, validate::check_response)
+}
+
+fn resource_in<R: TryResource>(
+    input: &str,
+    stdout: &mut impl Write,
+    context: InContext,
+) -> Result<(), Error> {
+    let input: InInput<Value, Value, Value> = parse_input(input)?;
+    let source = parse_section::<R, _>(input.source, Section::Source, context.logger())?;
+    let version = parse_section::<R, _>(Some(input.version), Section::Version, context.logger())?
+        .ok_or_else(|| Error::internal("version should be present"))?;
+    let params = parse_section::<R, _>(input.params, Section::Params, context.logger())?;
+
+    let InOutput { version, metadata } = R::try_resource_in(source, version, params, &context)?;
+
+    let (response, parsed) = serialize_response(
+        &InOutputKV {
+            version,
+            metadata: metadata_kv::<R>(metadata, context.logger())?,
+        },
+        validate::step_response,
+    )?;
+    if let Some(dir) = R::IN_OUTPUT_FILES {
+        let dir = context.output_dir().join(dir).map_err(Error::internal)?;
+        persist::write_in_output(&OutputDir::new(dir), &parsed).map_err(Error::internal)?;
+    }
+    writeln!(stdout, "{}", response).map_err(Error::internal)
+}
+
+fn resource_out<R: TryResource>(
+    input: &str,
+    stdout: &mut impl Write,
+    context: OutContext,
+) -> Result<(), Error> {
+    let input: OutInput<Value, Value> = parse_input(input)?;
+    let source = parse_section::<R, _>(input.source, Section::Source, context.logger())?;
+    let params = parse_section::<R, _>(input.params, Section::Params, context.logger())?;
+
+    let OutOutput { version, metadata } = R::try_resource_out(source, params, &context)?;
+
+    respond(
+        stdout,
+        &OutOutputKV {
+            version,
+            metadata: metadata_kv::<R>(metadata, context.logger())?,
+        },
+        validate::step_response,
+    )
+}
+
+/// Turn the metadata returned by the resource into entries, within the limits of the resource
+fn metadata_kv<R: TryResource>(
+    metadata: Option<impl IntoMetadataKV>,
+    logger: &Logger,
+) -> Result<Option<Vec<KV>>, Error> {
+    let mut metadata = match metadata {
+        Some(metadata) => metadata.try_into_metadata_kv().map_err(Error::internal)?,
+        None => return Ok(None),
+    };
+
+    let truncation = R::METADATA_LIMITS.apply(&mut metadata);
+    if truncation.dropped_entries > 0 {
+        logger.warn(format_args!(
+            "metadata has too many entries, the last {} were dropped",
+            truncation.dropped_entries
+        ));
+    }
+    for name in truncation.truncated_values {
+        logger.warn(format_args!(
+            "metadata `{}` is too long and was truncated",
+            name
+        ));
+    }
+    Ok(Some(metadata))
+}
+
+/// Parse the JSON payload sent by Concourse, keeping its sections as JSON
+fn parse_input<T: DeserializeOwned>(input: &str) -> Result<T, Error> {
+    serde_json::from_str(input).map_err(|error| {
+        Error::internal(error).with_hint("the input on stdin should be the JSON sent by Concourse")
+    })
+}
+
+/// Section of the payload sent by Concourse
+#[derive(Debug, Clone, Copy)]
+enum Section {
+    Source,
+    Params,
+    Version,
+}
+
+impl Section {
+    fn name(self) -> &'static str {
+        match self {
+            Section::Source => "source",
+            Section::Params => "params",
+            Section::Version => "version",
+        }
+    }
+
+    fn unknown_keys<R: TryResource>(self) -> UnknownKeys {
+        match self {
+            Section::Source => R::UNKNOWN_SOURCE_KEYS,
+            Section::Params => R::UNKNOWN_PARAMS_KEYS,
+            Section::Version => R::UNKNOWN_VERSION_KEYS,
+        }
+    }
+
+    fn renamed_keys<R: TryResource>(self) -> &'static [RenamedKey] {
+        match self {
+            Section::Source => R::RENAMED_SOURCE_KEYS,
+            Section::Params => R::RENAMED_PARAMS_KEYS,
+            Section::Version => &[],
+        }
+    }
+
+    fn error(self, cause: impl Into<Box<dyn std::error::Error>>) -> Error {
+        match self {
+            Section::Source => Error::invalid_source(cause),
+            Section::Params => Error::invalid_params(cause),
+            Section::Version => Error::invalid_version(cause),
+        }
+    }
+}
+
+/// Parse a section of the payload, migrating its renamed keys and handling its unknown keys as
+/// configured by the resource
+fn parse_section<R: TryResource, T: DeserializeOwned>(
+    value: Option<Value>,
+    section: Section,
+    logger: &Logger,
+) -> Result<Option<T>, Error> {
+    let mut value = match value {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let renamed_keys = renamed_key::migrate(&mut value, section.renamed_keys::<R>())
+        .map_err(|conflict| section.error(conflict))?;
+    for renamed_key in renamed_keys {
+        logger.warn(format_args!(
+            "`{}` in {} is deprecated, use `{}` instead",
+            renamed_key.old,
+            section.name(),
+            renamed_key.new
+        ));
+    }
+
+    let (parsed, unknown_keys) =
+        unknown_keys::deserialize(value).map_err(|error| section.error(SectionError(error)))?;
+
+    if !unknown_keys.is_empty() {
+        match section.unknown_keys::<R>() {
+            UnknownKeys::Ignore => (),
+            UnknownKeys::Warn => {
+                for key in unknown_keys {
+                    logger.warn(format_args!("unknown key in {}: {}", section.name(), key));
+                }
+            }
+            UnknownKeys::Fail => {
+                let keys: Vec<_> = unknown_keys.iter().map(ToString::to_string).collect();
+                return Err(section.error(format!("unknown keys {}", keys.join(", "))));
+            }
+        }
+    }
+    Ok(Some(parsed))
+}
+
+/// Error when deserializing a section of the payload, pointing at the offending JSON path
+#[derive(Debug)]
+struct SectionError(serde_path_to_error::Error<serde_json::Error>);
+
+impl fmt::Display for SectionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = self.0.path().to_string();
+        if path != "." {
+            write!(f, ".{}: ", path)?;
+        }
+        write!(f, "{}", self.0.inner())
+    }
+}
+
+impl std::error::Error for SectionError {}
+
+/// Write the response of the step, after checking that Concourse will accept it
+fn respond(
+    stdout: &mut impl Write,
+    response: &impl Serialize,
+    validate: fn(&Value) -> Result<(), InvalidResponse>,
+) -> Result<(), Error> {
+    let (response, _) = serialize_response(response, validate)?;
+    writeln!(stdout, "{}", response).map_err(Error::internal)
+}
+
+/// Serialize the response of the step, and parse it back to check that Concourse will accept it
+fn serialize_response(
+    response: &impl Serialize,
+    validate: fn(&Value) -> Result<(), InvalidResponse>,
+) -> Result<(String, Value), Error> {
+    let response = serde_json::to_string(response).map_err(Error::internal)?;
+    // validated as parsed back, so that the response is written with its fields in order
+    let parsed = serde_json::from_str(&response).map_err(Error::internal)?;
+    validate(&parsed).map_err(Error::internal)?;
+    Ok((response, parsed))
+}
@@ -0,0 +1,272 @@
+//! Error type returned by the methods of a `TryResource`
+
+use std::{fmt, io, process::ExitCode};
+
+use crate::{BuildMetadataError, MetadataError, VersionError};
+
+type Cause = Box<dyn std::error::Error>;
+
+/// Error returned by a `TryResource` step. When returned to the dispatcher built by
+/// `create_resource!`, it is printed on stderr with its causes and hint, and the process exits
+/// with the code of its class:
+///
+/// | class                                        | exit code |
+/// |----------------------------------------------|-----------|
+/// | [`Upstream`](#variant.Upstream)              | 1         |
+/// | [`InvalidSource`](#variant.InvalidSource)    | 3         |
+/// | [`InvalidParams`](#variant.InvalidParams)    | 4         |
+/// | [`InvalidVersion`](#variant.InvalidVersion)  | 5         |
+/// | [`Internal`](#variant.Internal)              | 70        |
+///
+/// When the binary is called with unexpected arguments, it exits with code 2.
+///
+/// Messages, boxed errors, `std::io::Error` and `serde_json::Error` can be converted into an
+/// `Upstream` error, so the `?` operator can be used on them, and a message can be used directly.
+/// Errors of this crate are converted into the matching class: `BuildMetadataError` and
+/// `MetadataError` are `Internal` errors, `VersionError` is an `InvalidVersion` error. Other
+/// errors can be classified with the constructors, such as [`upstream`](#method.upstream):
+///
+/// ```
+/// # use concourse_resource::Error;
+/// fn fails() -> Result<(), Error> {
+///     Err("upstream is not reachable".into())
+/// }
+///
+/// fn parses(retries: &str) -> Result<u8, Error> {
+///     retries.parse().map_err(Error::invalid_params)
+/// }
+/// ```
+pub enum Error {
+    /// The `source` configuration of the resource is invalid
+    InvalidSource {
+        /// What is invalid
+        cause: Cause,
+        /// How to fix it
+        hint: Option<String>,
+    },
+    /// The `params` of the step are invalid
+    InvalidParams {
+        /// What is invalid
+        cause: Cause,
+        /// How to fix it
+        hint: Option<String>,
+    },
+    /// The requested version is invalid, or doesn't exist anymore
+    InvalidVersion {
+        /// What is invalid
+        cause: Cause,
+        /// How to fix it
+        hint: Option<String>,
+    },
+    /// The system the resource is talking to failed
+    Upstream {
+        /// What failed
+        cause: Cause,
+        /// How to fix it
+        hint: Option<String>,
+    },
+    /// Bug in the resource or in the dispatcher
+    Internal {
+        /// What failed
+        cause: Cause,
+        /// How to fix it
+        hint: Option<String>,
+    },
+}
+
+impl Error {
+    /// Create an [`InvalidSource`](#variant.InvalidSource) error
+    pub fn invalid_source(cause: impl Into<Cause>) -> Self {
+        Error::InvalidSource {
+            cause: cause.into(),
+            hint: None,
+        }
+    }
+
+    /// Create an [`InvalidParams`](#variant.InvalidParams) error
+    pub fn invalid_params(cause: impl Into<Cause>) -> Self {
+        Error::InvalidParams {
+            cause: cause.into(),
+            hint: None,
+        }
+    }
+
+    /// Create an [`InvalidVersion`](#variant.InvalidVersion) error
+    pub fn invalid_version(cause: impl Into<Cause>) -> Self {
+        Error::InvalidVersion {
+            cause: cause.into(),
+            hint: None,
+        }
+    }
+
+    /// Create an [`Upstream`](#variant.Upstream) error
+    pub fn upstream(cause: impl Into<Cause>) -> Self {
+        Error::Upstream {
+            cause: cause.into(),
+            hint: None,
+        }
+    }
+
+    /// Create an [`Internal`](#variant.Internal) error
+    pub fn internal(cause: impl Into<Cause>) -> Self {
+        Error::Internal {
+            cause: cause.into(),
+            hint: None,
+        }
+    }
+
+    /// Add a hint on how to fix the error, printed after it
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        *self.hint_mut() = Some(hint.into());
+        self
+    }
+
+    /// The cause of the error
+    pub fn cause(&self) -> &(dyn std::error::Error + 'static) {
+        match self {
+            Error::InvalidSource { cause, .. }
+            | Error::InvalidParams { cause, .. }
+            | Error::InvalidVersion { cause, .. }
+            | Error::Upstream { cause, .. }
+            | Error::Internal { cause, .. } => cause.as_ref(),
+        }
+    }
+
+    /// The hint on how to fix the error
+    pub fn hint(&self) -> Option<&str> {
+        match self {
+            Error::InvalidSource { hint, .. }
+            | Error::InvalidParams { hint, .. }
+            | Error::InvalidVersion { hint, .. }
+            | Error::Upstream { hint, .. }
+            | Error::Internal { hint, .. } => hint.as_deref(),
+        }
+    }
+
+    fn hint_mut(&mut self) -> &mut Option<String> {
+        match self {
+            Error::InvalidSource { hint, .. }
+            | Error::InvalidParams { hint, .. }
+            | Error::InvalidVersion { hint, .. }
+            | Error::Upstream { hint, .. }
+            | Error::Internal { hint, .. } => hint,
+        }
+    }
+
+    /// Code the process exits with for this error
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Upstream { .. } => 1,
+            Error::InvalidSource { .. } => 3,
+            Error::InvalidParams { .. } => 4,
+            Error::InvalidVersion { .. } => 5,
+            Error::Internal { .. } => 70,
+        }
+    }
+
+    fn class(&self) -> Option<&'static str> {
+        match self {
+            Error::InvalidSource { .. } => Some("invalid source"),
+            Error::InvalidParams { .. } => Some("invalid params"),
+            Error::InvalidVersion { .. } => Some("invalid version"),
+            Error::Upstream { .. } => None,
+            Error::Internal { .. } => Some("internal error"),
+        }
+    }
+}
+
+impl From<&str> for Error {
+    fn from(cause: &str) -> Self {
+        Error::upstream(cause)
+    }
+}
+
+impl From<String> for Error {
+    fn from(cause: String) -> Self {
+        Error::upstream(cause)
+    }
+}
+
+impl From<Cause> for Error {
+    fn from(cause: Cause) -> Self {
+        Error::upstream(cause)
+    }
+}
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for Error {
+    fn from(cause: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        let cause: Cause = cause;
+        Error::upstream(cause)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(cause: io::Error) -> Self {
+        Error::upstream(cause)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(cause: serde_json::Error) -> Self {
+        Error::upstream(cause)
+    }
+}
+
+impl From<BuildMetadataError> for Error {
+    fn from(cause: BuildMetadataError) -> Self {
+        Error::internal(cause)
+    }
+}
+
+impl From<MetadataError> for Error {
+    fn from(cause: MetadataError) -> Self {
+        Error::internal(cause)
+    }
+}
+
+impl From<VersionError> for Error {
+    fn from(cause: VersionError) -> Self {
+        Error::invalid_version(cause)
+    }
+}
+
+impl From<&Error> for ExitCode {
+    fn from(error: &Error) -> Self {
+        ExitCode::from(error.exit_code())
+    }
+}
+
+/// Display the class and the cause of the error. The alternate format (`{:#}`) also displays the
+/// chain of sources of the cause, and the hint.
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(class) = self.class() {
+            write!(f, "{}: ", class)?;
+        }
+        write!(f, "{}", self.cause())?;
+
+        if f.alternate() {
+            let mut source = self.cause().source();
+            while let Some(cause) = source {
+                write!(f, "\ncaused by: {}", cause)?;
+                source = cause.source();
+            }
+            if let Some(hint) = self.hint() {
+                write!(f, "\nhint: {}", hint)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.cause())
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#}", self)
+    }
+}
@@ -0,0 +1,97 @@
+//! Installation of the `check`, `in` and `out` entrypoints next to the resource binary
+
+use std::{
+    fs, io,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+const ENTRYPOINTS: [&str; 3] = ["check", "in", "out"];
+
+/// Create the `check`, `in` and `out` entrypoints in `dir`, all pointing to the binary `exe`.
+/// Symlinks are used when possible, then hard links, then copies.
+///
+/// Entrypoints that already exist are kept if they are the same binary as `exe`, otherwise this
+/// fails.
+pub(crate) fn install(exe: &Path, dir: &Path, stderr: &mut impl Write) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    for entrypoint in ENTRYPOINTS.iter() {
+        let path = dir.join(entrypoint);
+        if fs::symlink_metadata(&path).is_ok() {
+            if !is_same_binary(&path, exe)? {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "{} already exists and is not {}",
+                        path.display(),
+                        exe.display()
+                    ),
+                ));
+            }
+            writeln!(stderr, "{} is already installed", path.display())?;
+        } else {
+            let kind = create_entrypoint(exe, &path)?;
+            writeln!(
+                stderr,
+                "installed {} as a {} to {}",
+                path.display(),
+                kind,
+                exe.display()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn create_entrypoint(exe: &Path, path: &Path) -> io::Result<&'static str> {
+    if symlink(&symlink_target(exe, path), path).is_ok() {
+        return Ok("symlink");
+    }
+    if fs::hard_link(exe, path).is_ok() {
+        return Ok("hard link");
+    }
+    fs::copy(exe, path).map(|_| "copy")
+}
+
+/// Link relatively if the entrypoint is next to the binary, so that the directory can be moved
+fn symlink_target(exe: &Path, path: &Path) -> PathBuf {
+    match (exe.parent(), path.parent(), exe.file_name()) {
+        (Some(exe_dir), Some(dir), Some(exe_name)) if same_path(exe_dir, dir) => {
+            PathBuf::from(exe_name)
+        }
+        _ => exe.to_path_buf(),
+    }
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn is_same_binary(path: &Path, exe: &Path) -> io::Result<bool> {
+    if same_path(path, exe) {
+        return Ok(true);
+    }
+    Ok(fs::read(path)? == fs::read(exe)?)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, path: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, path)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_target: &Path, _path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "symlinks are not supported on this platform",
+    ))
+}
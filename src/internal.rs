@@ -1,11 +1,20 @@
 //! Internal types used to wrap inputs and outputs. They should not be built
 //! directly but are used by macros
 
-use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, io::Write, str::FromStr};
+
+use serde::{
+    de::{IgnoredAny, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+
+use crate::{MetadataError, VersionError};
+
+#[doc(hidden)]
+pub use serde;
 
 /// Simple Key-Value struct as needed by Concourse for metadata
-#[allow(missing_debug_implementations)]
-#[derive(Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct KV {
     /// The name of this metadata
     pub name: String,
@@ -66,3 +75,141 @@ pub struct OutInput<S, P> {
     /// Step configuration, from the `params` field
     pub params: Option<P>,
 }
+
+/// Redirect the process stdout to stderr, and return a writer to the original stdout.
+///
+/// Concourse reads the response of the resource from stdout, so anything else printed there
+/// would corrupt it. After this is called, `println!` in the resource or in the libraries it uses
+/// goes to stderr and is shown in the build log, while the returned writer is kept for the
+/// response. This is the equivalent of `exec 3>&1 1>&2` in a shell resource.
+///
+/// If stdout can't be redirected, it is returned as is.
+pub fn protect_stdout() -> Box<dyn Write> {
+    #[cfg(unix)]
+    {
+        if let Some(stdout) = redirect_stdout_to_stderr() {
+            return Box::new(stdout);
+        }
+    }
+    Box::new(std::io::stdout())
+}
+
+#[cfg(unix)]
+#[allow(unsafe_code)]
+fn redirect_stdout_to_stderr() -> Option<std::fs::File> {
+    use std::os::unix::io::FromRawFd;
+
+    std::io::stdout().flush().ok()?;
+    // SAFETY: `dup` and `dup2` only manipulate the file descriptor table. The duplicated
+    // descriptor is owned by nothing else, so it can be given to a `File`.
+    unsafe {
+        let stdout = libc::dup(libc::STDOUT_FILENO);
+        if stdout < 0 {
+            return None;
+        }
+        if libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            libc::close(stdout);
+            return None;
+        }
+        Some(std::fs::File::from_raw_fd(stdout))
+    }
+}
+
+/// Value of the metadata field `name`, used by `#[derive(IntoMetadataKV)]`. Strings are kept as
+/// is, `None` has no value, and other values are rendered as JSON.
+pub fn metadata_value<T: Serialize + ?Sized>(
+    name: &str,
+    value: &T,
+) -> Result<Option<String>, MetadataError> {
+    serde_json::to_value(value)
+        .map(json_metadata_value)
+        .map_err(|cause| MetadataError::new(name, cause))
+}
+
+pub(crate) fn json_metadata_value(value: serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(value) => Some(value),
+        value => Some(value.to_string()),
+    }
+}
+
+/// Prefix the name of the field of `error` with the name of the field it was flattened in, used
+/// by `#[derive(IntoMetadataKV)]`
+pub fn flattened_metadata_error(prefix: &str, error: MetadataError) -> MetadataError {
+    error.prefixed(prefix)
+}
+
+/// Unwrap the metadata, used by `#[derive(IntoMetadataKV)]` for the infallible conversion
+pub fn expect_metadata(metadata: Result<Vec<KV>, MetadataError>) -> Vec<KV> {
+    metadata.unwrap_or_else(|error| panic!("{}: {}", error, error.cause()))
+}
+
+/// Parse the field `field` of a version from `map`, used by `#[derive(ConcourseVersion)]`
+pub fn parse_version_field<T>(
+    map: &mut BTreeMap<String, String>,
+    field: &'static str,
+) -> Result<T, VersionError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let value = map.remove(field).ok_or(VersionError::MissingField(field))?;
+    value
+        .parse()
+        .map_err(|cause: T::Err| VersionError::InvalidField {
+            field,
+            cause: cause.to_string(),
+            value,
+        })
+}
+
+/// Deserialize the keys `fields` of the version `name` as a map of strings, used by
+/// `#[derive(ConcourseVersion)]`. Other keys are skipped as ignored values, so that they are
+/// reported like unknown keys of a serde struct.
+pub fn deserialize_version_map<'de, D>(
+    deserializer: D,
+    name: &'static str,
+    fields: &'static [&'static str],
+) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_struct(name, fields, VersionMapVisitor { fields })
+}
+
+struct VersionMapVisitor {
+    fields: &'static [&'static str],
+}
+
+impl<'de> Visitor<'de> for VersionMapVisitor {
+    type Value = BTreeMap<String, String>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of strings")
+    }
+
+    fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut map = BTreeMap::new();
+        while let Some(key) = access.next_key::<String>()? {
+            if self.fields.contains(&key.as_str()) {
+                let value = access.next_value()?;
+                map.insert(key, value);
+            } else {
+                access.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(map)
+    }
+}
+
+/// Check that `T` can be a field of a version, used by `#[derive(ConcourseVersion)]`
+pub fn assert_version_field<T>()
+where
+    T: fmt::Display + FromStr,
+    T::Err: fmt::Display,
+{
+}
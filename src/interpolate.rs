@@ -0,0 +1,190 @@
+//! Interpolation of `$BUILD_*` variables in params, as done by Concourse official resources
+//!
+//! `$NAME` and `${NAME}` are replaced by the value of the build metadata variable `NAME`, and
+//! `$$` by a single `$`. A `$` that is not followed by a letter, `_` or `{` is kept as is.
+//! Variables that are known but not set for the build, like `BUILD_JOB_NAME` for a one-off build,
+//! are replaced by an empty string. Unknown variables are an error.
+//!
+//! Params can be interpolated when they are deserialized, from the environment of the step:
+//!
+//! ```
+//! # use serde::Deserialize;
+//! #[derive(Deserialize)]
+//! struct OutParams {
+//!     #[serde(deserialize_with = "concourse_resource::interpolate::deserialize")]
+//!     tag: String,
+//! }
+//! ```
+//!
+//! or kept as [`Interpolated`](struct.Interpolated.html) and interpolated explicitly.
+
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{dispatch, BuildMetadata};
+
+/// Names of the variables that can be interpolated
+pub const VARIABLES: [&str; 7] = [
+    "BUILD_ID",
+    "BUILD_NAME",
+    "BUILD_JOB_NAME",
+    "BUILD_PIPELINE_NAME",
+    "BUILD_PIPELINE_INSTANCE_VARS",
+    "BUILD_TEAM_NAME",
+    "ATC_EXTERNAL_URL",
+];
+
+/// Error when interpolating a string
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterpolationError {
+    /// The variable is not one of [`VARIABLES`](constant.VARIABLES.html)
+    UnknownVariable(String),
+    /// A `${` is not closed
+    Unterminated,
+}
+
+impl fmt::Display for InterpolationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterpolationError::UnknownVariable(name) => write!(
+                f,
+                "unknown variable ${}, expected one of ${}, or $$ for a literal $",
+                name,
+                VARIABLES.join(", $")
+            ),
+            InterpolationError::Unterminated => write!(f, "missing closing }} after ${{"),
+        }
+    }
+}
+
+impl std::error::Error for InterpolationError {}
+
+/// A string param in which `$BUILD_*` variables will be interpolated
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interpolated {
+    template: String,
+}
+
+impl Interpolated {
+    /// Create from a template
+    pub fn new(template: impl Into<String>) -> Self {
+        Interpolated {
+            template: template.into(),
+        }
+    }
+
+    /// The template, before interpolation
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// Interpolate the variables in the template from the build metadata
+    ///
+    /// ```
+    /// # use concourse_resource::{BuildMetadata, Interpolated};
+    /// let metadata = BuildMetadata::builder().id("42").pipeline_name("release").build();
+    /// let tag = Interpolated::new("$BUILD_PIPELINE_NAME-${BUILD_ID}");
+    /// assert_eq!(tag.interpolate(&metadata).unwrap(), "release-42");
+    /// ```
+    pub fn interpolate(&self, metadata: &BuildMetadata) -> Result<String, InterpolationError> {
+        interpolate(&self.template, |name| match name {
+            "BUILD_ID" => Some(metadata.id.clone()),
+            "BUILD_NAME" => metadata.name.clone(),
+            "BUILD_JOB_NAME" => metadata.job_name.clone(),
+            "BUILD_PIPELINE_NAME" => metadata.pipeline_name.clone(),
+            "BUILD_PIPELINE_INSTANCE_VARS" => metadata
+                .pipeline_instance_vars
+                .as_ref()
+                .map(|vars| serde_json::Value::from(vars.clone()).to_string()),
+            "BUILD_TEAM_NAME" => Some(metadata.team_name.clone()),
+            "ATC_EXTERNAL_URL" => Some(metadata.atc_external_url.clone()),
+            _ => None,
+        })
+    }
+
+    /// Interpolate the variables in the template from the environment of the step
+    pub fn interpolate_env(&self) -> Result<String, InterpolationError> {
+        interpolate(&self.template, dispatch::env_var)
+    }
+}
+
+impl<'de> Deserialize<'de> for Interpolated {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer).map(Interpolated::new)
+    }
+}
+
+impl Serialize for Interpolated {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.template.serialize(serializer)
+    }
+}
+
+/// Deserialize a string, interpolating its variables from the environment of the step. To be
+/// used with `#[serde(deserialize_with = "concourse_resource::interpolate::deserialize")]`.
+pub fn deserialize<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Interpolated::deserialize(deserializer)?
+        .interpolate_env()
+        .map_err(serde::de::Error::custom)
+}
+
+/// Deserialize an optional string, interpolating its variables from the environment of the
+/// step. To be used with
+/// `#[serde(default, deserialize_with = "concourse_resource::interpolate::deserialize_option")]`.
+pub fn deserialize_option<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<Interpolated>::deserialize(deserializer)?
+        .map(|template| template.interpolate_env())
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+fn interpolate(
+    template: &str,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<String, InterpolationError> {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(dollar) = rest.find('$') {
+        result.push_str(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+
+        let (name, after) = if let Some(after) = rest.strip_prefix('$') {
+            result.push('$');
+            rest = after;
+            continue;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced.find('}').ok_or(InterpolationError::Unterminated)?;
+            (&braced[..end], &braced[end + 1..])
+        } else if rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        } else {
+            // like in a shell, a `$` that does not start a name is kept as is
+            result.push('$');
+            continue;
+        };
+
+        if !VARIABLES.contains(&name) {
+            return Err(InterpolationError::UnknownVariable(name.to_string()));
+        }
+        result.push_str(&var(name).unwrap_or_default());
+        rest = after;
+    }
+    result.push_str(rest);
+    Ok(result)
+}
@@ -15,12 +15,36 @@
 //!
 //! [Concourse documentation](https://concourse-ci.org/implementing-resource-types.html)
 
+use std::time::Duration;
+
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use serde_json::{Map, Value};
 
 pub use concourse_resource_derive::*;
 
+mod build_metadata;
+pub use build_metadata::{BuildMetadata, BuildMetadataBuilder, BuildMetadataError};
+mod context;
+pub use context::{CheckContext, InContext, Logger, OutContext};
+mod dispatch;
+pub use dispatch::run;
+mod error;
+pub use error::Error;
+mod install;
 pub mod internal;
+pub mod interpolate;
+pub use interpolate::Interpolated;
+mod metadata;
+pub use metadata::{MetadataBuilder, MetadataError, MetadataLimits};
+mod persist;
+mod renamed_key;
+pub use renamed_key::RenamedKey;
+mod step_dir;
+pub use step_dir::{EscapingPath, InputDir, OutputDir};
+mod unknown_keys;
+pub use unknown_keys::UnknownKeys;
+mod validate;
+mod version;
+pub use version::{ConcourseVersion, VersionError};
 
 /// Output of the "in" step of the resource
 #[allow(missing_debug_implementations)]
@@ -46,13 +70,74 @@ pub struct OutOutput<V, M> {
 
 /// Trait for Metadata to be usable as Concourse Metadata. This trait can be derived if the
 /// base struct implement `serde::Deserialize`
+///
+/// For metadata only known at runtime, it is also implemented for `Vec<KV>`, `Vec<(K, V)>`,
+/// `BTreeMap<K, V>` and `HashMap<K, V>` (sorted by name) with keys and values that are strings,
+/// `serde_json::Map`, `Option<T>` and [`MetadataBuilder`](struct.MetadataBuilder.html).
+///
+/// The derive honors `#[serde(rename, skip, skip_serializing_if, rename_all)]`, which can be
+/// overridden for metadata only:
+///
+/// ```
+/// # use concourse_resource::IntoMetadataKV;
+/// # use serde::Serialize;
+/// #[derive(Serialize, IntoMetadataKV)]
+/// #[metadata(rename_all = "Title Case")]
+/// struct Metadata {
+///     commit_sha: String,
+///     #[metadata(rename = "MR")]
+///     mr_iid: u64,
+///     #[metadata(skip)]
+///     token: String,
+///     #[metadata(skip_if = "Option::is_none")]
+///     reviewer: Option<String>,
+/// }
+/// ```
+///
+/// `rename_all` accepts the same cases as serde, and `"Title Case"`.
+///
+/// Strings are used as is, `None` fields are omitted and other values are rendered as JSON. A
+/// field can instead be rendered with its `Display` implementation with `#[metadata(display)]`,
+/// or with a function taking a reference to it and returning a `String` with
+/// `#[metadata(with = "path::to::fn")]`.
+///
+/// Fields that are not a single value can be split in several entries:
+/// - `#[metadata(flatten)]` on a field implementing `IntoMetadataKV` adds its entries, with their
+///   names prefixed by `<field>.`, or by the `prefix = "..."` option
+/// - `#[metadata(each)]` on a collection adds an entry per element, named `<field>[<index>]`
+/// - `#[metadata(join = ", ")]` on a collection adds a single entry with the elements joined by
+///   the separator, omitted if the collection is empty
+///
+/// Fields of tuple structs are named by their position. For an enum, an entry `variant` (or the
+/// name given by `#[metadata(tag = "...")]` or `#[serde(tag = "...")]`) holds the name of the
+/// variant, followed by the entries of its fields. As with serde, `rename_all` on an enum renames
+/// its variants.
+///
+/// As with serde, the derive bounds the type parameters used by serialized fields with
+/// `Serialize`, and those used by displayed fields with `Display`. The type of a flattened field
+/// is bounded with `IntoMetadataKV`.
+///
+/// The generated code refers to this crate as `::concourse_resource`. When it is renamed or
+/// re-exported, its path can be given with `#[metadata(crate = "path::to::crate")]`.
 pub trait IntoMetadataKV {
     /// Turn `self` into a `Vec` of `internal::KV`
     fn into_metadata_kv(self) -> Vec<internal::KV>;
+
+    /// Turn `self` into a `Vec` of `internal::KV`, failing if a field can't be serialized. This is
+    /// what the dispatcher built by `create_resource!` uses, so that such a field fails the step
+    /// with an error naming it instead of a panic.
+    ///
+    /// By default, this calls the infallible [`into_metadata_kv`](#tymethod.into_metadata_kv).
+    fn try_into_metadata_kv(self) -> Result<Vec<internal::KV>, MetadataError>
+    where
+        Self: Sized,
+    {
+        Ok(self.into_metadata_kv())
+    }
 }
 
 /// Empty value that can be used as `InParams`, `InMetadata`, `OutParams` or `OutMetadata` for
-/// a `Resource`
+/// a `Resource` or a `TryResource`
 #[allow(missing_debug_implementations)]
 #[derive(Serialize, Deserialize, Copy, Clone, Default)]
 pub struct Empty;
@@ -62,34 +147,129 @@ impl IntoMetadataKV for Empty {
     }
 }
 
-/// When used in a "get" or "put" step, metadata about the running build is made available
-/// via environment variables.
-///
-/// If the build is a one-off, `name`, `job_name`, `pipeline_name`, and `pipeline_instance_vars`
-/// will be `None`. `pipeline_instance_vars` will also be `None` if the build's pipeline is not a
-/// pipeline instance (i.e. it is a regular pipeline).
+/// The methods, associated types and configuration needed to implement a resource whose steps
+/// are given a context and can fail
 ///
-/// [Concourse documentation](https://concourse-ci.org/implementing-resource-types.html#resource-metadata)
-#[derive(Debug)]
-pub struct BuildMetadata {
-    /// The internal identifier for the build. Right now this is numeric but it may become
-    /// a guid in the future. Treat it as an absolute reference to the build.
-    pub id: String,
-    /// The build number within the build's job.
-    pub name: Option<String>,
-    /// The name of the build's job.
-    pub job_name: Option<String>,
-    /// The pipeline that the build's job lives in.
-    pub pipeline_name: Option<String>,
-    /// The pipeline's instance vars, used to differentiate pipeline instances.
-    pub pipeline_instance_vars: Option<Map<String, Value>>,
-    /// The team that the build belongs to.
-    pub team_name: String,
-    /// The public URL for your ATC; useful for debugging.
-    pub atc_external_url: String,
+/// Each step must be implemented, a resource that does not support a step can return an error
+/// from it. Every [`Resource`](trait.Resource.html) is also a `TryResource` with the default
+/// configuration.
+pub trait TryResource {
+    /// A version of the resource
+    type Version: Serialize + DeserializeOwned;
+
+    /// Resource configuration, from the `source` field
+    type Source: DeserializeOwned;
+
+    /// Parameters for the "in" step, from the `params` field
+    type InParams: DeserializeOwned;
+    /// A list of key-value pairs for the "in" step. This data is intended for public
+    /// consumption and will make it upstream, intended to be shown on the build's page.
+    type InMetadata: Serialize + IntoMetadataKV;
+
+    /// Parameters for the "out" step, from the `params` field
+    type OutParams: DeserializeOwned;
+    /// A list of key-value pairs for the "out" step. This data is intended for public
+    /// consumption and will make it upstream, intended to be shown on the build's page.
+    type OutMetadata: Serialize + IntoMetadataKV;
+
+    /// Directory where the resource is installed in its image, `/opt/resource` for Concourse. The
+    /// step is selected from the name of the binary whatever its directory, this is used by the
+    /// `install` subcommand when no directory is given, and in the usage help when the binary is
+    /// called incorrectly.
+    const INSTALL_PREFIX: &'static str = "/opt/resource";
+
+    /// How to handle keys of the `source` that are not used by the resource
+    const UNKNOWN_SOURCE_KEYS: UnknownKeys = UnknownKeys::Ignore;
+    /// How to handle keys of the `params` that are not used by the resource
+    const UNKNOWN_PARAMS_KEYS: UnknownKeys = UnknownKeys::Ignore;
+    /// How to handle keys of the `version` that are not used by the resource
+    const UNKNOWN_VERSION_KEYS: UnknownKeys = UnknownKeys::Ignore;
+
+    /// Keys of the `source` that were renamed, including keys of nested objects. The old names
+    /// are still accepted, with a deprecation warning in the build log.
+    const RENAMED_SOURCE_KEYS: &'static [RenamedKey] = &[];
+    /// Keys of the `params` that were renamed, including keys of nested objects. The old names
+    /// are still accepted, with a deprecation warning in the build log.
+    const RENAMED_PARAMS_KEYS: &'static [RenamedKey] = &[];
+
+    /// Limits applied to the metadata of the "in" and "out" steps, with a warning in the build
+    /// log when it is truncated. Metadata is not limited by default, see
+    /// [`MetadataLimits::RECOMMENDED`](struct.MetadataLimits.html#associatedconstant.RECOMMENDED).
+    const METADATA_LIMITS: MetadataLimits = MetadataLimits::UNLIMITED;
+
+    /// Directory, relative to the destination of the "in" step, where the fetched version and
+    /// metadata are written for the next steps of the build, or `None` to not write them. `""`
+    /// writes them at the root of the destination. The directory contains:
+    ///
+    /// - `version.json`: the version, as returned to Concourse
+    /// - `metadata.json`: the metadata, as a list of `{"name": ..., "value": ...}`
+    /// - `version/<key>`: the value of each key of the version
+    /// - `metadata/<name>`: the value of each metadata entry, one per line for entries sharing
+    ///   the same name
+    ///
+    /// `/`, `\` and control characters in keys and names are replaced by `_`.
+    const IN_OUTPUT_FILES: Option<&'static str> = None;
+
+    /// Maximum duration of a step. When set, the [`deadline`](struct.InContext.html#method.deadline)
+    /// of the step context is this long after the resource was started.
+    const STEP_TIMEOUT: Option<Duration> = None;
+
+    /// A resource type's check method is invoked to detect new versions of the resource. It is
+    /// given the configured source and current version, and must return the array of new
+    /// versions, in chronological order, including the requested version if it's still valid.
+    ///
+    /// If the check fails, the method must return an error.
+    ///
+    /// [Concourse documentation](https://concourse-ci.org/implementing-resource-types.html#resource-check)
+    fn try_resource_check(
+        source: Option<Self::Source>,
+        version: Option<Self::Version>,
+        context: &CheckContext,
+    ) -> Result<Vec<Self::Version>, Error>;
+
+    /// The in method is passed the configured source, a precise version of the resource to fetch
+    /// and a destination directory, available from the `context`. The method must fetch the
+    /// resource and place it in the given directory.
+    ///
+    /// If the desired resource version is unavailable (for example, if it was deleted), the
+    /// method must return an error.
+    ///
+    /// The method must return the fetched version, and may return metadata as a list of
+    /// key-value pairs. This data is intended for public consumption and will make it upstream,
+    /// intended to be shown on the build's page.
+    ///
+    /// [Concourse documentation](https://concourse-ci.org/implementing-resource-types.html#in)
+    fn try_resource_in(
+        source: Option<Self::Source>,
+        version: Self::Version,
+        params: Option<Self::InParams>,
+        context: &InContext,
+    ) -> Result<InOutput<Self::Version, Self::InMetadata>, Error>;
+
+    /// The out method is called with the resource's source configuration, the configured params
+    /// and a path to the directory containing the build's full set of sources, available from
+    /// the `context`.
+    ///
+    /// The script must return the resulting version of the resource. Additionally, it may return
+    /// metadata as a list of key-value pairs. This data is intended for public consumption and
+    /// will make it upstream, intended to be shown on the build's page.
+    ///
+    /// If the put fails, the method must return an error.
+    ///
+    /// [Concourse documentation](https://concourse-ci.org/implementing-resource-types.html#out)
+    fn try_resource_out(
+        source: Option<Self::Source>,
+        params: Option<Self::OutParams>,
+        context: &OutContext,
+    ) -> Result<OutOutput<Self::Version, Self::OutMetadata>, Error>;
 }
 
 /// The methods and associated types needed to implement a resource
+///
+/// Every `Resource` is a [`TryResource`](trait.TryResource.html) with the default configuration,
+/// which is what [`run`](fn.run.html) and `create_resource!` take. Implement `TryResource`
+/// instead to return errors from the "check" and "out" steps, use the step contexts or change
+/// the configuration.
 pub trait Resource {
     /// A version of the resource
     type Version: Serialize + DeserializeOwned;
@@ -155,102 +335,100 @@ pub trait Resource {
     /// When used in a "get" or "put" step, will return [metadata](struct.BuildMetadata.html) about the running build is
     /// made available via environment variables.
     ///
+    /// # Panics
+    ///
+    /// If the metadata can't be read from the environment, for example in a "check" step.
+    ///
     /// [Concourse documentation](https://concourse-ci.org/implementing-resource-types.html#resource-metadata)
+    #[deprecated(
+        since = "0.4.0",
+        note = "use `build_metadata` from the step context, or `BuildMetadata::from_env`"
+    )]
     fn build_metadata() -> BuildMetadata {
-        BuildMetadata {
-            id: std::env::var("BUILD_ID").expect("environment variable BUILD_ID should be present"),
-            name: std::env::var("BUILD_NAME").ok(),
-            job_name: std::env::var("BUILD_JOB_NAME").ok(),
-            pipeline_name: std::env::var("BUILD_PIPELINE_NAME").ok(),
-            pipeline_instance_vars: std::env::var("BUILD_PIPELINE_INSTANCE_VARS")
-                .ok()
-                .and_then(|instance_vars| serde_json::from_str(&instance_vars[..]).ok()),
-            team_name: std::env::var("BUILD_TEAM_NAME")
-                .expect("environment variable BUILD_TEAM_NAME should be present"),
-            atc_external_url: std::env::var("ATC_EXTERNAL_URL")
-                .expect("environment variable ATC_EXTERNAL_URL should be present"),
+        match BuildMetadata::from_env() {
+            Ok(metadata) => metadata,
+            Err(error) => panic!("{}", error),
         }
     }
 }
 
-/// Macro that will build the `main` function from a struct implementing the `Resource` trait
+impl<T: Resource> TryResource for T {
+    type Version = T::Version;
+    type Source = T::Source;
+    type InParams = T::InParams;
+    type InMetadata = T::InMetadata;
+    type OutParams = T::OutParams;
+    type OutMetadata = T::OutMetadata;
+
+    fn try_resource_check(
+        source: Option<Self::Source>,
+        version: Option<Self::Version>,
+        _context: &CheckContext,
+    ) -> Result<Vec<Self::Version>, Error> {
+        Ok(T::resource_check(source, version))
+    }
+
+    fn try_resource_in(
+        source: Option<Self::Source>,
+        version: Self::Version,
+        params: Option<Self::InParams>,
+        context: &InContext,
+    ) -> Result<InOutput<Self::Version, Self::InMetadata>, Error> {
+        Ok(T::resource_in(
+            source,
+            version,
+            params,
+            &context.output_path().to_string_lossy(),
+        )?)
+    }
+
+    fn try_resource_out(
+        source: Option<Self::Source>,
+        params: Option<Self::OutParams>,
+        context: &OutContext,
+    ) -> Result<OutOutput<Self::Version, Self::OutMetadata>, Error> {
+        Ok(T::resource_out(
+            source,
+            params,
+            &context.input_path().to_string_lossy(),
+        ))
+    }
+}
+
+/// Macro that will build the `main` function from a struct implementing the `Resource` or the
+/// `TryResource` trait
+///
+/// The `main` function calls [`run`](fn.run.html) with the process arguments, standard streams
+/// and environment. The step to run is selected from the name of the binary (`check`, `in` or
+/// `out`, whatever directory it is in), or from a subcommand:
+///
+/// ```sh
+/// main check
+/// main in <destination directory>
+/// main out <sources directory>
+/// ```
+///
+/// The binary can also create the `check`, `in` and `out` entrypoints next to it, in
+/// [`TryResource::INSTALL_PREFIX`](trait.TryResource.html#associatedconstant.INSTALL_PREFIX) or
+/// in the given directory:
+///
+/// ```sh
+/// main install [directory]
+/// ```
+///
+/// The process stdout is redirected to stderr before running the step, so that only the response
+/// is written to the real stdout, see [`protect_stdout`](internal/fn.protect_stdout.html).
 #[macro_export]
 macro_rules! create_resource {
     ($resource:ty) => {
-        use std::io::Read;
-
-        use concourse_resource::internal::*;
-
-        fn main() {
-            let mut input_buffer = String::new();
-            let stdin = std::io::stdin();
-            let mut handle = stdin.lock();
-
-            handle.read_to_string(&mut input_buffer).unwrap();
-
-            let mut args = std::env::args();
-
-            match args.next().expect("should have a bin name").as_ref() {
-                "/opt/resource/check" => {
-                    let input: CheckInput<
-                        <$resource as Resource>::Source,
-                        <$resource as Resource>::Version,
-                    > = serde_json::from_str(&input_buffer).expect("error deserializing input");
-                    let result =
-                        <$resource as Resource>::resource_check(input.source, input.version);
-                    println!(
-                        "{}",
-                        serde_json::to_string(&result).expect("error serializing output")
-                    );
-                }
-                "/opt/resource/in" => {
-                    let input: InInput<
-                        <$resource as Resource>::Source,
-                        <$resource as Resource>::Version,
-                        <$resource as Resource>::InParams,
-                    > = serde_json::from_str(&input_buffer).expect("error deserializing input");
-                    let result = <$resource as Resource>::resource_in(
-                        input.source,
-                        input.version,
-                        input.params,
-                        &args.next().expect("expected path as first parameter"),
-                    );
-                    match result {
-                        Err(error) => {
-                            eprintln!("Error! {}", error);
-                            std::process::exit(1);
-                        }
-                        Ok(InOutput { version, metadata }) => println!(
-                            "{}",
-                            serde_json::to_string(&InOutputKV {
-                                version,
-                                metadata: metadata.map(|md| md.into_metadata_kv())
-                            })
-                            .expect("error serializing output")
-                        ),
-                    };
-                }
-                "/opt/resource/out" => {
-                    let input: OutInput<
-                        <$resource as Resource>::Source,
-                        <$resource as Resource>::OutParams,
-                    > = serde_json::from_str(&input_buffer).expect("error deserializing input");
-                    let result = <$resource as Resource>::resource_out(
-                        input.source,
-                        input.params,
-                        &args.next().expect("expected path as first parameter"),
-                    );
-                    println!(
-                        "{}",
-                        serde_json::to_string(&OutOutputKV {
-                            version: result.version,
-                            metadata: result.metadata.map(|md| md.into_metadata_kv())
-                        })
-                        .expect("error serializing output")
-                    );
-                }
-                v => eprintln!("unexpected being called as '{}'", v),
-            }
+        fn main() -> std::process::ExitCode {
+            $crate::run::<$resource>(
+                std::env::args_os(),
+                std::io::stdin(),
+                $crate::internal::protect_stdout(),
+                std::io::stderr(),
+                std::env::vars_os(),
+            )
         }
     };
 }
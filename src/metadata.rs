@@ -0,0 +1,323 @@
+//! Implementations of `IntoMetadataKV` for std types, and a builder for metadata known at runtime
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    hash::BuildHasher,
+    iter::Peekable,
+    str::Chars,
+};
+
+use serde::Serialize;
+
+use crate::{
+    internal::{json_metadata_value, KV},
+    IntoMetadataKV,
+};
+
+fn kv(name: impl Into<String>, value: impl Into<String>) -> KV {
+    KV {
+        name: name.into(),
+        value: value.into(),
+    }
+}
+
+impl IntoMetadataKV for Vec<KV> {
+    fn into_metadata_kv(self) -> Vec<KV> {
+        self
+    }
+}
+
+/// Entries are kept in order
+impl<K: Into<String>, V: Into<String>> IntoMetadataKV for Vec<(K, V)> {
+    fn into_metadata_kv(self) -> Vec<KV> {
+        self.into_iter()
+            .map(|(name, value)| kv(name, value))
+            .collect()
+    }
+}
+
+/// Entries are sorted by name
+impl<K: Into<String>, V: Into<String>> IntoMetadataKV for BTreeMap<K, V> {
+    fn into_metadata_kv(self) -> Vec<KV> {
+        self.into_iter()
+            .map(|(name, value)| kv(name, value))
+            .collect()
+    }
+}
+
+/// Entries are sorted by name, so that they don't change order between builds
+impl<K: Into<String>, V: Into<String>, S: BuildHasher> IntoMetadataKV for HashMap<K, V, S> {
+    fn into_metadata_kv(self) -> Vec<KV> {
+        let mut metadata: Vec<_> = self
+            .into_iter()
+            .map(|(name, value)| kv(name, value))
+            .collect();
+        metadata.sort_by(|a, b| a.name.cmp(&b.name));
+        metadata
+    }
+}
+
+/// Strings are used as is, `null` values are omitted and other values are rendered as JSON
+impl IntoMetadataKV for serde_json::Map<String, serde_json::Value> {
+    fn into_metadata_kv(self) -> Vec<KV> {
+        self.into_iter()
+            .filter_map(|(name, value)| json_metadata_value(value).map(|value| kv(name, value)))
+            .collect()
+    }
+}
+
+/// `None` has no entries
+impl<T: IntoMetadataKV> IntoMetadataKV for Option<T> {
+    fn into_metadata_kv(self) -> Vec<KV> {
+        self.map(IntoMetadataKV::into_metadata_kv)
+            .unwrap_or_default()
+    }
+
+    fn try_into_metadata_kv(self) -> Result<Vec<KV>, MetadataError> {
+        self.map(IntoMetadataKV::try_into_metadata_kv)
+            .unwrap_or_else(|| Ok(vec![]))
+    }
+}
+
+/// Error when a field of the metadata could not be serialized
+#[derive(Debug)]
+pub struct MetadataError {
+    field: String,
+    cause: serde_json::Error,
+}
+
+impl MetadataError {
+    pub(crate) fn new(field: &str, cause: serde_json::Error) -> Self {
+        MetadataError {
+            field: field.to_string(),
+            cause,
+        }
+    }
+
+    pub(crate) fn prefixed(mut self, prefix: &str) -> Self {
+        self.field.insert_str(0, prefix);
+        self
+    }
+
+    /// Name of the metadata entry of the field
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    /// Why the field could not be serialized
+    pub fn cause(&self) -> &serde_json::Error {
+        &self.cause
+    }
+}
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "could not serialize metadata `{}`", self.field)
+    }
+}
+
+impl std::error::Error for MetadataError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.cause)
+    }
+}
+
+/// Metadata built by pushing entries, for entries only known at runtime
+///
+/// ```
+/// # use concourse_resource::{IntoMetadataKV, MetadataBuilder};
+/// let mut metadata = MetadataBuilder::new();
+/// for artifact in &["app.tar.gz", "app.sha256"] {
+///     metadata.push("artifact", *artifact);
+/// }
+/// metadata.push("uploaded", "2");
+/// assert_eq!(metadata.into_metadata_kv().len(), 3);
+/// ```
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(transparent)]
+pub struct MetadataBuilder {
+    metadata: Vec<KV>,
+}
+
+impl MetadataBuilder {
+    /// Create an empty builder
+    pub fn new() -> Self {
+        MetadataBuilder::default()
+    }
+
+    /// Add an entry
+    pub fn push(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.metadata.push(kv(name, value));
+        self
+    }
+
+    /// Add the entries of `metadata`
+    pub fn extend(&mut self, metadata: impl IntoMetadataKV) -> &mut Self {
+        self.metadata.extend(metadata.into_metadata_kv());
+        self
+    }
+
+    /// Whether no entries were added
+    pub fn is_empty(&self) -> bool {
+        self.metadata.is_empty()
+    }
+}
+
+impl IntoMetadataKV for MetadataBuilder {
+    fn into_metadata_kv(self) -> Vec<KV> {
+        self.metadata
+    }
+}
+
+/// Limits applied by the dispatcher to the metadata of the "in" and "out" steps. Concourse stores
+/// metadata in its database and shows it on the build page, so it should stay small.
+///
+/// Metadata is not limited by default, [`RECOMMENDED`](#associatedconstant.RECOMMENDED) limits
+/// can be opted into and adjusted:
+///
+/// ```
+/// # use concourse_resource::MetadataLimits;
+/// const METADATA_LIMITS: MetadataLimits = MetadataLimits {
+///     max_entries: Some(20),
+///     ..MetadataLimits::RECOMMENDED
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetadataLimits {
+    /// Maximum number of entries, the following ones are dropped
+    pub max_entries: Option<usize>,
+    /// Maximum number of characters of a value, longer values are truncated to end with a
+    /// marker saying how many characters were removed, within the maximum
+    pub max_value_length: Option<usize>,
+    /// Remove control characters other than newlines and tabs from names and values, with the
+    /// ANSI escape sequences they start
+    pub strip_control_characters: bool,
+}
+
+impl MetadataLimits {
+    /// At most 100 entries of 1024 characters, without control characters
+    pub const RECOMMENDED: MetadataLimits = MetadataLimits {
+        max_entries: Some(100),
+        max_value_length: Some(1024),
+        strip_control_characters: true,
+    };
+
+    /// Metadata is left untouched, the default
+    pub const UNLIMITED: MetadataLimits = MetadataLimits {
+        max_entries: None,
+        max_value_length: None,
+        strip_control_characters: false,
+    };
+
+    /// Apply the limits to `metadata`, returning what was truncated
+    pub(crate) fn apply(&self, metadata: &mut Vec<KV>) -> Truncation {
+        let mut truncation = Truncation::default();
+        if let Some(max_entries) = self.max_entries {
+            truncation.dropped_entries = metadata.len().saturating_sub(max_entries);
+            metadata.truncate(max_entries);
+        }
+        for kv in metadata {
+            if self.strip_control_characters {
+                strip_control_characters(&mut kv.name);
+                strip_control_characters(&mut kv.value);
+            }
+            if let Some(max_value_length) = self.max_value_length {
+                if truncate(&mut kv.value, max_value_length) {
+                    truncation.truncated_values.push(kv.name.clone());
+                }
+            }
+        }
+        truncation
+    }
+}
+
+impl Default for MetadataLimits {
+    fn default() -> Self {
+        MetadataLimits::UNLIMITED
+    }
+}
+
+/// Truncate `value` to at most `max_length` characters, including the marker saying how many
+/// characters were removed, returning whether it was truncated. When the marker does not fit, the
+/// value is cut without it.
+fn truncate(value: &mut String, max_length: usize) -> bool {
+    let length = value.chars().count();
+    if length <= max_length {
+        return false;
+    }
+    let marker = |kept: usize| format!("… ({} characters truncated)", length - kept);
+    let mut kept = max_length;
+    let mut suffix = marker(kept);
+    // a longer marker leaves less room for the value, which can lengthen the marker again
+    while kept > 0 && kept + suffix.chars().count() > max_length {
+        kept = max_length.saturating_sub(suffix.chars().count());
+        suffix = marker(kept);
+    }
+    if kept + suffix.chars().count() > max_length {
+        kept = max_length;
+        suffix = String::new();
+    }
+    let end = value
+        .char_indices()
+        .nth(kept)
+        .map_or(value.len(), |(index, _)| index);
+    value.truncate(end);
+    value.push_str(&suffix);
+    true
+}
+
+/// Remove control characters other than newlines and tabs from `text`, with the whole CSI (like
+/// colors) and OSC (like hyperlinks) escape sequences they start
+fn strip_control_characters(text: &mut String) {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{1b}' if chars.peek() == Some(&'[') => {
+                chars.next();
+                skip_csi(&mut chars);
+            }
+            '\u{9b}' => skip_csi(&mut chars),
+            '\u{1b}' if chars.peek() == Some(&']') => {
+                chars.next();
+                skip_osc(&mut chars);
+            }
+            '\u{9d}' => skip_osc(&mut chars),
+            c if !c.is_control() || c == '\n' || c == '\t' => stripped.push(c),
+            _ => (),
+        }
+    }
+    *text = stripped;
+}
+
+/// Skip the rest of a CSI sequence: parameter and intermediate bytes, then the final byte
+fn skip_csi(chars: &mut Peekable<Chars>) {
+    while let Some('\u{20}'..='\u{3f}') = chars.peek() {
+        chars.next();
+    }
+    if let Some('\u{40}'..='\u{7e}') = chars.peek() {
+        chars.next();
+    }
+}
+
+/// Skip the rest of an OSC sequence, up to the BEL or string terminator ending it
+fn skip_osc(chars: &mut Peekable<Chars>) {
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{7}' | '\u{9c}' => return,
+            '\u{1b}' => {
+                chars.next_if_eq(&'\\');
+                return;
+            }
+            _ => (),
+        }
+    }
+}
+
+/// What was removed from metadata by [`MetadataLimits`](struct.MetadataLimits.html)
+#[derive(Debug, Default)]
+pub(crate) struct Truncation {
+    pub(crate) dropped_entries: usize,
+    pub(crate) truncated_values: Vec<String>,
+}
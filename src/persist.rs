@@ -0,0 +1,85 @@
+//! Persistence of the response of the "in" step into its destination directory
+
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use crate::OutputDir;
+
+/// Error when writing a file of the response
+#[derive(Debug)]
+pub(crate) struct PersistError {
+    path: PathBuf,
+    cause: io::Error,
+}
+
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "could not write {}", self.path.display())
+    }
+}
+
+impl std::error::Error for PersistError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.cause)
+    }
+}
+
+/// Write the version and metadata of the validated `response` of an "in" step into `dir`, with
+/// the layout documented on `TryResource::IN_OUTPUT_FILES`
+pub(crate) fn write_in_output(dir: &OutputDir, response: &Value) -> Result<(), PersistError> {
+    let version = &response["version"];
+    let metadata = match &response["metadata"] {
+        Value::Null => Value::Array(vec![]),
+        metadata => metadata.clone(),
+    };
+
+    write(dir, Path::new("version.json"), version.to_string())?;
+    write(dir, Path::new("metadata.json"), metadata.to_string())?;
+
+    if let Value::Object(fields) = version {
+        for (name, value) in fields {
+            let value = value.as_str().unwrap_or_default();
+            write(dir, &Path::new("version").join(file_name(name)), value)?;
+        }
+    }
+
+    let mut entries: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for kv in metadata.as_array().into_iter().flatten() {
+        let name = kv["name"].as_str().unwrap_or_default();
+        let value = kv["value"].as_str().unwrap_or_default();
+        entries.entry(file_name(name)).or_default().push(value);
+    }
+    for (name, values) in entries {
+        write(dir, &Path::new("metadata").join(name), values.join("\n"))?;
+    }
+    Ok(())
+}
+
+fn write(dir: &OutputDir, path: &Path, contents: impl AsRef<[u8]>) -> Result<(), PersistError> {
+    dir.write(path, contents).map_err(|cause| PersistError {
+        path: dir.path().join(path),
+        cause,
+    })
+}
+
+/// `name` usable as a file name, with path separators and control characters replaced by `_`
+fn file_name(name: &str) -> String {
+    match name {
+        "" | "." | ".." => name.replace('.', "_") + "_",
+        name => name
+            .chars()
+            .map(|c| {
+                if c == '/' || c == '\\' || c.is_control() {
+                    '_'
+                } else {
+                    c
+                }
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,118 @@
+//! Migration of keys of the input that were renamed
+
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+/// Key of the `source` or `params` that was renamed. Pipelines still using the old key keep
+/// working: its value is moved to the new key before the input is deserialized, and a deprecation
+/// warning is shown in the build log.
+///
+/// ```
+/// # use concourse_resource::RenamedKey;
+/// const RENAMED_SOURCE_KEYS: &[RenamedKey] = &[RenamedKey::new("repo", "repository")];
+/// ```
+///
+/// Keys of nested objects are given by their path, with `.` between keys and `[]` after a key
+/// holding a list of objects. Only the last key of the path is renamed, the parent path of the
+/// new key is the one of the old key:
+///
+/// ```
+/// # use concourse_resource::RenamedKey;
+/// const RENAMED_PARAMS_KEYS: &[RenamedKey] = &[
+///     RenamedKey::new("files[].pattern", "files[].glob"),
+///     RenamedKey::new("git.ref", "git.branch"),
+/// ];
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenamedKey {
+    /// Deprecated name of the key
+    pub old: &'static str,
+    /// Current name of the key
+    pub new: &'static str,
+}
+
+impl RenamedKey {
+    /// Key `old` was renamed to `new`
+    pub const fn new(old: &'static str, new: &'static str) -> Self {
+        RenamedKey { old, new }
+    }
+}
+
+/// Error when both the old and the new name of a renamed key are set
+#[derive(Debug)]
+pub(crate) struct Conflict(RenamedKey);
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "both `{}` and `{}` are set, `{}` is the deprecated name of `{}`",
+            self.0.old, self.0.new, self.0.old, self.0.new
+        )
+    }
+}
+
+impl std::error::Error for Conflict {}
+
+/// Move the values of the old keys of `value` to their new name, returning the keys that were
+/// moved
+pub(crate) fn migrate(
+    value: &mut Value,
+    renamed_keys: &[RenamedKey],
+) -> Result<Vec<RenamedKey>, Conflict> {
+    let mut migrated = vec![];
+    for renamed_key in renamed_keys {
+        let (parent, old) = split_path(renamed_key.old);
+        let (_, new) = split_path(renamed_key.new);
+        let mut moved = false;
+        for object in objects_at(value, &parent) {
+            if let Some(old_value) = object.remove(old) {
+                if object.contains_key(new) {
+                    return Err(Conflict(*renamed_key));
+                }
+                object.insert(new.to_string(), old_value);
+                moved = true;
+            }
+        }
+        if moved {
+            migrated.push(*renamed_key);
+        }
+    }
+    Ok(migrated)
+}
+
+/// Path of the parent of `path` as a list of keys, and the last key of `path`
+fn split_path(path: &str) -> (Vec<&str>, &str) {
+    let mut keys: Vec<_> = path.split('.').collect();
+    let last = keys.pop().unwrap_or_default();
+    (keys, last)
+}
+
+/// Objects found at `path` in `value`, a key ending with `[]` going through each element of the
+/// list it holds
+fn objects_at<'a>(value: &'a mut Value, path: &[&str]) -> Vec<&'a mut Map<String, Value>> {
+    let mut current = vec![value];
+    for key in path.iter().copied() {
+        let (key, each) = match key.strip_suffix("[]") {
+            Some(key) => (key, true),
+            None => (key, false),
+        };
+        let mut next = vec![];
+        for value in current {
+            match (value.get_mut(key), each) {
+                (Some(Value::Array(elements)), true) => next.extend(elements.iter_mut()),
+                (Some(child), false) => next.push(child),
+                _ => (),
+            }
+        }
+        current = next;
+    }
+    current
+        .into_iter()
+        .filter_map(|value| match value {
+            Value::Object(object) => Some(object),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,209 @@
+//! Directories given to the "in" and "out" steps
+
+use std::{
+    ffi::OsString,
+    fmt, fs, io,
+    path::{Component, Path, PathBuf},
+    process,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Error when a path given relative to a step directory would point outside of it, because it
+/// is absolute or goes up with `..` past the root of the directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EscapingPath {
+    path: PathBuf,
+}
+
+impl EscapingPath {
+    /// Path that was rejected
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl fmt::Display for EscapingPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "path `{}` is outside of the step directory",
+            self.path.display()
+        )
+    }
+}
+
+impl std::error::Error for EscapingPath {}
+
+impl From<EscapingPath> for io::Error {
+    fn from(error: EscapingPath) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, error)
+    }
+}
+
+/// Destination directory of an "in" step, where the resource must be fetched
+///
+/// Paths given to its methods are relative to the directory, and rejected with an
+/// [`EscapingPath`](struct.EscapingPath.html) error when they would point outside of it.
+///
+/// ```
+/// # use concourse_resource::OutputDir;
+/// # let root = std::env::temp_dir().join(format!("output-dir-doc-{}", std::process::id()));
+/// let dir = OutputDir::new(&root);
+/// dir.write_text("release/tag", "v1.2.0")?;
+/// assert_eq!(std::fs::read_to_string(root.join("release/tag"))?, "v1.2.0");
+/// assert!(dir.join("../elsewhere").is_err());
+/// # std::fs::remove_dir_all(root)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputDir {
+    root: PathBuf,
+}
+
+impl OutputDir {
+    /// Destination directory at `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        OutputDir { root: path.into() }
+    }
+
+    /// Path of the directory
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of `path` inside the directory
+    pub fn join(&self, path: impl AsRef<Path>) -> Result<PathBuf, EscapingPath> {
+        join_within(&self.root, path.as_ref())
+    }
+
+    /// Write `contents` to the file at `path`, creating the missing parent directories. The file
+    /// is written next to its final location then renamed, so that it is never seen partially
+    /// written.
+    pub fn write(&self, path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+        let path = self.join(path)?;
+        let (parent, name) = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => (parent, name),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("`{}` is not a file path", path.display()),
+                ))
+            }
+        };
+        fs::create_dir_all(parent)?;
+
+        let mut temporary_name = OsString::from(".");
+        temporary_name.push(name);
+        temporary_name.push(format!(".{}.tmp", process::id()));
+        let temporary = parent.join(temporary_name);
+        let written = fs::write(&temporary, contents).and_then(|_| fs::rename(&temporary, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+        written
+    }
+
+    /// Write `text` to the file at `path`, like [`write`](#method.write)
+    pub fn write_text(&self, path: impl AsRef<Path>, text: impl AsRef<str>) -> io::Result<()> {
+        self.write(path, text.as_ref())
+    }
+
+    /// Write `value` serialized as JSON to the file at `path`, like [`write`](#method.write)
+    pub fn write_json(&self, path: impl AsRef<Path>, value: &impl Serialize) -> io::Result<()> {
+        self.write(path, serde_json::to_vec(value)?)
+    }
+}
+
+impl AsRef<Path> for OutputDir {
+    fn as_ref(&self) -> &Path {
+        &self.root
+    }
+}
+
+/// Directory of an "out" step, containing the build's full set of sources: one directory per
+/// artifact of the build, named after it
+///
+/// Paths given to its methods are relative to the directory, and rejected with an
+/// [`EscapingPath`](struct.EscapingPath.html) error when they would point outside of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputDir {
+    root: PathBuf,
+}
+
+impl InputDir {
+    /// Sources directory at `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        InputDir { root: path.into() }
+    }
+
+    /// Path of the directory
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of `path` inside the directory
+    pub fn join(&self, path: impl AsRef<Path>) -> Result<PathBuf, EscapingPath> {
+        join_within(&self.root, path.as_ref())
+    }
+
+    /// Names of the artifacts available to the step, sorted
+    pub fn artifacts(&self) -> io::Result<Vec<OsString>> {
+        let mut artifacts = vec![];
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            if fs::metadata(entry.path())?.is_dir() {
+                artifacts.push(entry.file_name());
+            }
+        }
+        artifacts.sort();
+        Ok(artifacts)
+    }
+
+    /// Contents of the file at `path`
+    pub fn read(&self, path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+        fs::read(self.join(path)?)
+    }
+
+    /// Contents of the file at `path`, which must be valid UTF-8
+    pub fn read_text(&self, path: impl AsRef<Path>) -> io::Result<String> {
+        fs::read_to_string(self.join(path)?)
+    }
+
+    /// Contents of the file at `path`, deserialized from JSON
+    pub fn read_json<T: DeserializeOwned>(&self, path: impl AsRef<Path>) -> io::Result<T> {
+        Ok(serde_json::from_slice(&self.read(path)?)?)
+    }
+}
+
+impl AsRef<Path> for InputDir {
+    fn as_ref(&self) -> &Path {
+        &self.root
+    }
+}
+
+/// Join `path` to `root`, resolving `.` and `..` without following symlinks, as long as it stays
+/// inside `root`
+fn join_within(root: &Path, path: &Path) -> Result<PathBuf, EscapingPath> {
+    let escaping = || EscapingPath {
+        path: path.to_path_buf(),
+    };
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => relative.push(name),
+            Component::CurDir => (),
+            Component::ParentDir => {
+                if !relative.pop() {
+                    return Err(escaping());
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return Err(escaping()),
+        }
+    }
+    if relative.as_os_str().is_empty() {
+        Ok(root.to_path_buf())
+    } else {
+        Ok(root.join(relative))
+    }
+}
@@ -0,0 +1,259 @@
+//! Detection of keys of the input that are ignored when deserializing it
+
+use std::{collections::HashMap, fmt};
+
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    forward_to_deserialize_any, Deserializer,
+};
+use serde_json::Value;
+
+/// How to handle keys in a section of the input (`source`, `params` or `version`) that are not
+/// used by the resource, usually typos in the pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownKeys {
+    /// Unknown keys are silently ignored
+    Ignore,
+    /// Unknown keys are listed in a warning in the build log
+    Warn,
+    /// Unknown keys fail the step
+    Fail,
+}
+
+/// Key of the input that was ignored when deserializing it
+#[derive(Debug)]
+pub(crate) struct UnknownKey {
+    path: String,
+    suggestion: Option<&'static str>,
+}
+
+impl fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path)?;
+        if let Some(suggestion) = self.suggestion {
+            write!(f, " (did you mean `{}`?)", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Deserialize `value`, also returning the keys that were ignored
+pub(crate) fn deserialize<T: DeserializeOwned>(
+    value: Value,
+) -> Result<(T, Vec<UnknownKey>), serde_path_to_error::Error<serde_json::Error>> {
+    let mut ignored = vec![];
+    let mut on_ignored =
+        |path: serde_ignored::Path| ignored.push((format_path(&path), parent_and_key(&path)));
+    let deserialized = serde_path_to_error::deserialize(serde_ignored::Deserializer::new(
+        value.clone(),
+        &mut on_ignored,
+    ))?;
+
+    let fields = if ignored.is_empty() {
+        HashMap::new()
+    } else {
+        struct_fields::<T>(value)
+    };
+    let unknown = ignored
+        .into_iter()
+        .map(|(path, parent_and_key)| UnknownKey {
+            path,
+            suggestion: parent_and_key.and_then(|(parent, key)| {
+                fields.get(&parent).and_then(|fields| suggest(&key, fields))
+            }),
+        })
+        .collect();
+    Ok((deserialized, unknown))
+}
+
+fn format_path(path: &serde_ignored::Path) -> String {
+    match path {
+        serde_ignored::Path::Root => String::new(),
+        serde_ignored::Path::Seq { parent, index } => format!("{}[{}]", format_path(parent), index),
+        serde_ignored::Path::Map { parent, key } => format!("{}.{}", format_path(parent), key),
+        serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => format_path(parent),
+    }
+}
+
+/// Path of the object containing the key at `path`, and the key
+fn parent_and_key(path: &serde_ignored::Path) -> Option<(String, String)> {
+    match path {
+        serde_ignored::Path::Map { parent, key } => Some((format_path(parent), key.clone())),
+        _ => None,
+    }
+}
+
+/// Closest known field to `key`, if it is close enough to be a typo
+fn suggest(key: &str, fields: &'static [&'static str]) -> Option<&'static str> {
+    fields
+        .iter()
+        .map(|field| (distance(key, field), *field))
+        .filter(|(distance, _)| *distance <= 1.max(key.chars().count() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, field)| field)
+}
+
+/// Levenshtein distance between `a` and `b`
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + if a == *b { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Fields of the structs deserialized from `value` as `T`, by path, so that unknown keys nested
+/// in the value can also be compared to their siblings. This second pass can fail where the first
+/// one succeeded, for example on maps with integer keys, and then only has the fields of the
+/// structs deserialized until then.
+fn struct_fields<T: DeserializeOwned>(value: Value) -> HashMap<String, &'static [&'static str]> {
+    let mut fields = HashMap::new();
+    let _ = T::deserialize(FieldsCollector {
+        value,
+        path: String::new(),
+        fields: &mut fields,
+    });
+    fields
+}
+
+/// Deserializer of a JSON value recording the fields of the structs deserialized from it, and
+/// their path
+struct FieldsCollector<'a> {
+    value: Value,
+    path: String,
+    fields: &'a mut HashMap<String, &'static [&'static str]>,
+}
+
+impl<'de> Deserializer<'de> for FieldsCollector<'_> {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Object(object) => visitor.visit_map(ObjectCollector {
+                entries: object.into_iter(),
+                value: None,
+                path: self.path,
+                fields: self.fields,
+            }),
+            Value::Array(array) => visitor.visit_seq(ArrayCollector {
+                elements: array.into_iter().enumerate(),
+                path: self.path,
+                fields: self.fields,
+            }),
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.fields.insert(self.path.clone(), fields);
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.value.deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map identifier ignored_any
+    }
+}
+
+struct ObjectCollector<'a> {
+    entries: serde_json::map::IntoIter,
+    value: Option<(String, Value)>,
+    path: String,
+    fields: &'a mut HashMap<String, &'static [&'static str]>,
+}
+
+impl<'de> MapAccess<'de> for ObjectCollector<'_> {
+    type Error = serde_json::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                let deserialized = seed.deserialize(Value::String(key.clone()))?;
+                self.value = Some((key, value));
+                Ok(Some(deserialized))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let (key, value) = self
+            .value
+            .take()
+            .ok_or_else(|| de::Error::custom("value is missing"))?;
+        seed.deserialize(FieldsCollector {
+            value,
+            path: format!("{}.{}", self.path, key),
+            fields: self.fields,
+        })
+    }
+}
+
+struct ArrayCollector<'a> {
+    elements: std::iter::Enumerate<std::vec::IntoIter<Value>>,
+    path: String,
+    fields: &'a mut HashMap<String, &'static [&'static str]>,
+}
+
+impl<'de> SeqAccess<'de> for ArrayCollector<'_> {
+    type Error = serde_json::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        match self.elements.next() {
+            Some((index, value)) => seed
+                .deserialize(FieldsCollector {
+                    value,
+                    path: format!("{}[{}]", self.path, index),
+                    fields: self.fields,
+                })
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+}
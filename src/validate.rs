@@ -0,0 +1,90 @@
+//! Validation of the responses of the resource against the rules of Concourse
+
+use std::fmt;
+
+use serde_json::Value;
+
+/// Part of a response that Concourse would reject
+#[derive(Debug)]
+pub(crate) struct InvalidResponse {
+    path: String,
+    problem: String,
+}
+
+impl fmt::Display for InvalidResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid response, `{}` {}", self.path, self.problem)
+    }
+}
+
+impl std::error::Error for InvalidResponse {}
+
+/// Validate the response of a "check" step: a list of versions
+pub(crate) fn check_response(response: &Value) -> Result<(), InvalidResponse> {
+    match response {
+        Value::Array(versions) => versions
+            .iter()
+            .enumerate()
+            .try_for_each(|(index, version)| validate_version(&format!("[{}]", index), version)),
+        response => Err(invalid(".", response, "a list of versions")),
+    }
+}
+
+/// Validate the response of an "in" or "out" step: a version and its metadata
+pub(crate) fn step_response(response: &Value) -> Result<(), InvalidResponse> {
+    validate_version(".version", &response["version"])?;
+    match &response["metadata"] {
+        Value::Null => Ok(()),
+        Value::Array(metadata) => {
+            for (index, kv) in metadata.iter().enumerate() {
+                let path = format!(".metadata[{}].name", index);
+                match &kv["name"] {
+                    Value::String(name) if name.is_empty() => {
+                        return Err(InvalidResponse {
+                            path,
+                            problem: String::from("is empty, but metadata must be named"),
+                        })
+                    }
+                    Value::String(_) => (),
+                    name => return Err(invalid(&path, name, "a string")),
+                }
+            }
+            Ok(())
+        }
+        metadata => Err(invalid(".metadata", metadata, "a list")),
+    }
+}
+
+/// A version must be a flat object of string values
+fn validate_version(path: &str, version: &Value) -> Result<(), InvalidResponse> {
+    match version {
+        Value::Object(fields) => {
+            for (name, value) in fields {
+                if !value.is_string() {
+                    return Err(invalid(
+                        &format!("{}.{}", path, name),
+                        value,
+                        "a string, versions are flat objects of string values",
+                    ));
+                }
+            }
+            Ok(())
+        }
+        version => Err(invalid(path, version, "an object")),
+    }
+}
+
+fn invalid(path: &str, value: &Value, expected: &str) -> InvalidResponse {
+    let kind = match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "a list",
+        Value::Object(_) => "an object",
+    };
+    InvalidResponse {
+        path: path.to_string(),
+        problem: format!("is {}, but should be {}", kind, expected),
+    }
+}
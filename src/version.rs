@@ -0,0 +1,94 @@
+//! Versions with the shape required by Concourse
+
+use std::{collections::BTreeMap, fmt};
+
+/// Version of a resource as Concourse expects it: a flat object of string values. This trait
+/// can be derived for a struct with named fields that all implement `Display` and `FromStr`. The
+/// derive also implements `Serialize` and `Deserialize`, serializing each field as a string, and
+/// conversions to and from `BTreeMap<String, String>`.
+///
+/// ```
+/// # use concourse_resource::ConcourseVersion;
+/// #[derive(ConcourseVersion, Debug, PartialEq)]
+/// struct Version {
+///     tag: String,
+///     build: u64,
+/// }
+///
+/// let version = Version { tag: String::from("v1"), build: 12 };
+/// assert_eq!(
+///     serde_json::to_string(&version).unwrap(),
+///     r#"{"build":"12","tag":"v1"}"#
+/// );
+/// ```
+///
+/// The key of a field can be changed with `#[version(rename = "...")]`. As the derive
+/// implements `Serialize` and `Deserialize` itself, `#[serde(...)]` attributes are rejected:
+///
+/// ```compile_fail
+/// # use concourse_resource::ConcourseVersion;
+/// #[derive(ConcourseVersion)]
+/// struct Version {
+///     #[serde(rename = "ref")]
+///     reference: String,
+/// }
+/// ```
+///
+/// When deserialized, keys that are not fields of the version are handled as configured by
+/// [`TryResource::UNKNOWN_VERSION_KEYS`](trait.TryResource.html#associatedconstant.UNKNOWN_VERSION_KEYS).
+///
+/// The generated code refers to this crate as `::concourse_resource`. When it is renamed or
+/// re-exported, its path can be given with `#[version(crate = "path::to::crate")]`.
+///
+/// Fields that are not scalars are rejected at compile time:
+///
+/// ```compile_fail
+/// # use concourse_resource::ConcourseVersion;
+/// #[derive(ConcourseVersion)]
+/// struct Version {
+///     tags: Vec<String>,
+/// }
+/// ```
+pub trait ConcourseVersion: Sized {
+    /// Turn `self` into a map of strings
+    fn to_version_map(&self) -> BTreeMap<String, String>;
+
+    /// Read the version from a map of strings. Keys that are not fields of the version are
+    /// ignored.
+    fn from_version_map(map: BTreeMap<String, String>) -> Result<Self, VersionError>;
+}
+
+/// Error when reading a [`ConcourseVersion`](trait.ConcourseVersion.html) from a map of strings
+#[derive(Debug, Clone, PartialEq)]
+pub enum VersionError {
+    /// A field of the version is missing from the map
+    MissingField(&'static str),
+    /// The value of a field could not be parsed
+    InvalidField {
+        /// Name of the field
+        field: &'static str,
+        /// Value in the map
+        value: String,
+        /// Why it could not be parsed
+        cause: String,
+    },
+}
+
+impl fmt::Display for VersionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VersionError::MissingField(field) => write!(f, "missing field `{}`", field),
+            VersionError::InvalidField {
+                field,
+                value,
+                cause,
+            } => write!(
+                f,
+                "invalid value `{}` for field `{}`: {}",
+                value, field, cause
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VersionError {}
@@ -0,0 +1,113 @@
+use concourse_resource::{BuildMetadata, BuildMetadataError};
+
+#[test]
+fn missing_variable_is_an_error() {
+    let error =
+        BuildMetadata::from_map(vec![("BUILD_ID", "42"), ("BUILD_TEAM_NAME", "main")]).unwrap_err();
+
+    assert!(matches!(
+        error,
+        BuildMetadataError::MissingVariable("ATC_EXTERNAL_URL")
+    ));
+    assert_eq!(
+        error.to_string(),
+        "environment variable ATC_EXTERNAL_URL should be present"
+    );
+}
+
+#[test]
+fn invalid_instance_vars_is_an_error() {
+    let error = BuildMetadata::from_map(vec![
+        ("BUILD_ID", "42"),
+        ("BUILD_TEAM_NAME", "main"),
+        ("ATC_EXTERNAL_URL", "https://ci.example.com"),
+        ("BUILD_PIPELINE_INSTANCE_VARS", "{branch: main}"),
+    ])
+    .unwrap_err();
+
+    assert!(matches!(error, BuildMetadataError::InvalidInstanceVars(_)));
+}
+
+#[test]
+fn from_map_reads_instance_vars() {
+    let metadata = BuildMetadata::from_map(vec![
+        ("BUILD_ID", "42"),
+        ("BUILD_NAME", "7"),
+        ("BUILD_JOB_NAME", "test"),
+        ("BUILD_PIPELINE_NAME", "release"),
+        ("BUILD_PIPELINE_INSTANCE_VARS", r#"{"branch":"main"}"#),
+        ("BUILD_TEAM_NAME", "main"),
+        ("ATC_EXTERNAL_URL", "https://ci.example.com"),
+    ])
+    .unwrap();
+
+    assert_eq!(
+        metadata,
+        BuildMetadata::builder()
+            .id("42")
+            .name("7")
+            .job_name("test")
+            .pipeline_name("release")
+            .pipeline_instance_var("branch", "main")
+            .atc_external_url("https://ci.example.com")
+            .build()
+    );
+}
+
+#[test]
+fn urls_of_a_pipeline_build() {
+    let metadata = BuildMetadata::builder()
+        .id("1234")
+        .atc_external_url("https://ci.example.com/")
+        .team_name("my team")
+        .pipeline_name("release")
+        .job_name("say hello")
+        .name("12.1")
+        .build();
+
+    assert_eq!(
+        metadata.build_url(),
+        "https://ci.example.com/teams/my%20team/pipelines/release/jobs/say%20hello/builds/12.1"
+    );
+    assert_eq!(
+        metadata.job_url().unwrap(),
+        "https://ci.example.com/teams/my%20team/pipelines/release/jobs/say%20hello"
+    );
+    assert_eq!(
+        metadata.pipeline_url().unwrap(),
+        "https://ci.example.com/teams/my%20team/pipelines/release"
+    );
+    assert_eq!(
+        metadata.team_url(),
+        "https://ci.example.com/?search=team%3A%20my%20team"
+    );
+}
+
+#[test]
+fn urls_of_a_pipeline_instance_build() {
+    let metadata = BuildMetadata::builder()
+        .pipeline_name("release")
+        .pipeline_instance_var("version", serde_json::json!({"major": 1, "minor": "2"}))
+        .pipeline_instance_var("branch", "main")
+        .job_name("publish")
+        .name("3")
+        .build();
+
+    assert_eq!(
+        metadata.build_url(),
+        "http://localhost:8080/teams/main/pipelines/release/jobs/publish/builds/3?vars.branch=%22main%22&vars.version.major=1&vars.version.minor=%222%22"
+    );
+    assert_eq!(
+        metadata.pipeline_url().unwrap(),
+        "http://localhost:8080/teams/main/pipelines/release?vars.branch=%22main%22&vars.version.major=1&vars.version.minor=%222%22"
+    );
+}
+
+#[test]
+fn urls_of_a_one_off_build() {
+    let metadata = BuildMetadata::builder().id("1234").build();
+
+    assert_eq!(metadata.build_url(), "http://localhost:8080/builds/1234");
+    assert_eq!(metadata.job_url(), None);
+    assert_eq!(metadata.pipeline_url(), None);
+}
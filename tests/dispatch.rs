@@ -0,0 +1,906 @@
+use std::{path::Path, process::ExitCode};
+
+use concourse_resource::*;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct Version {
+    ver: String,
+}
+
+#[derive(Serialize, IntoMetadataKV)]
+struct Metadata {
+    build: String,
+}
+
+/// Steps of the resources of these tests, failing unless they are implemented
+trait Steps: TryResource {
+    fn check(
+        _: Option<Self::Source>,
+        _: Option<Self::Version>,
+        _: &CheckContext,
+    ) -> Result<Vec<Self::Version>, Error> {
+        Err("check is not supported".into())
+    }
+
+    fn get(
+        _: Option<Self::Source>,
+        _: Self::Version,
+        _: Option<Self::InParams>,
+        _: &InContext,
+    ) -> Result<InOutput<Self::Version, Self::InMetadata>, Error> {
+        Err("get is not supported".into())
+    }
+
+    fn put(
+        _: Option<Self::Source>,
+        _: Option<Self::OutParams>,
+        _: &OutContext,
+    ) -> Result<OutOutput<Self::Version, Self::OutMetadata>, Error> {
+        Err("put is not supported".into())
+    }
+}
+
+/// Declare a resource with the given types and configuration, running its `Steps`
+macro_rules! test_resource {
+    ($name:ident {
+        $(type $assoc:ident = $ty:ty;)*
+        $(const $config:ident: $config_ty:ty = $value:expr;)*
+    }) => {
+        struct $name;
+
+        impl TryResource for $name {
+            $(type $assoc = $ty;)*
+            $(const $config: $config_ty = $value;)*
+
+            fn try_resource_check(
+                source: Option<Self::Source>,
+                version: Option<Self::Version>,
+                context: &CheckContext,
+            ) -> Result<Vec<Self::Version>, Error> {
+                <Self as Steps>::check(source, version, context)
+            }
+
+            fn try_resource_in(
+                source: Option<Self::Source>,
+                version: Self::Version,
+                params: Option<Self::InParams>,
+                context: &InContext,
+            ) -> Result<InOutput<Self::Version, Self::InMetadata>, Error> {
+                <Self as Steps>::get(source, version, params, context)
+            }
+
+            fn try_resource_out(
+                source: Option<Self::Source>,
+                params: Option<Self::OutParams>,
+                context: &OutContext,
+            ) -> Result<OutOutput<Self::Version, Self::OutMetadata>, Error> {
+                <Self as Steps>::put(source, params, context)
+            }
+        }
+    };
+}
+
+test_resource!(TestResource {
+    type Version = Version;
+    type Source = Empty;
+    type InParams = Empty;
+    type InMetadata = Metadata;
+    type OutParams = Empty;
+    type OutMetadata = Empty;
+});
+
+impl Steps for TestResource {
+    fn check(
+        _: Option<Self::Source>,
+        version: Option<Self::Version>,
+        _: &CheckContext,
+    ) -> Result<Vec<Self::Version>, Error> {
+        match version {
+            Some(version) if version.ver == "broken" => Err("upstream is broken".into()),
+            Some(version) if version.ver == "panic" => panic!("check panicked"),
+            _ => Ok(vec![Version {
+                ver: String::from("1"),
+            }]),
+        }
+    }
+
+    fn get(
+        _: Option<Self::Source>,
+        version: Self::Version,
+        _: Option<Self::InParams>,
+        context: &InContext,
+    ) -> Result<InOutput<Self::Version, Self::InMetadata>, Error> {
+        assert_eq!(context.output_path(), Path::new("/tmp/build/get"));
+        assert_eq!(context.output_dir(), &OutputDir::new("/tmp/build/get"));
+        // the context can be shared with worker threads
+        std::thread::scope(|scope| {
+            scope.spawn(|| context.logger().info("fetching"));
+        });
+        // outside of the context, the build metadata is also read from the environment of `run`
+        assert_eq!(BuildMetadata::from_env()?.id, context.build_metadata()?.id);
+        Ok(InOutput {
+            version,
+            metadata: Some(Metadata {
+                build: context.build_metadata()?.id,
+            }),
+        })
+    }
+}
+
+struct OldResource;
+
+impl Resource for OldResource {
+    type Version = Version;
+
+    type Source = Empty;
+
+    type InParams = Empty;
+    type InMetadata = Empty;
+
+    type OutParams = Empty;
+    type OutMetadata = Empty;
+
+    fn resource_check(_: Option<Self::Source>, _: Option<Self::Version>) -> Vec<Self::Version> {
+        vec![Version {
+            ver: String::from("legacy"),
+        }]
+    }
+
+    fn resource_in(
+        _: Option<Self::Source>,
+        version: Self::Version,
+        _: Option<Self::InParams>,
+        output_path: &str,
+    ) -> Result<InOutput<Self::Version, Self::InMetadata>, Box<dyn std::error::Error>> {
+        assert_eq!(output_path, "/tmp/build/get");
+        Ok(InOutput {
+            version,
+            metadata: None,
+        })
+    }
+
+    fn resource_out(
+        _: Option<Self::Source>,
+        _: Option<Self::OutParams>,
+        input_path: &str,
+    ) -> OutOutput<Self::Version, Self::OutMetadata> {
+        OutOutput {
+            version: Version {
+                ver: input_path.to_string(),
+            },
+            metadata: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct NumberedVersion {
+    number: Option<u64>,
+}
+
+test_resource!(InvalidOutputResource {
+    type Version = NumberedVersion;
+    type Source = Empty;
+    type InParams = Empty;
+    type InMetadata = Metadata;
+    type OutParams = Empty;
+    type OutMetadata = Empty;
+});
+
+impl Steps for InvalidOutputResource {
+    fn check(
+        _: Option<Self::Source>,
+        _: Option<Self::Version>,
+        _: &CheckContext,
+    ) -> Result<Vec<Self::Version>, Error> {
+        Ok(vec![NumberedVersion { number: None }])
+    }
+
+    fn get(
+        _: Option<Self::Source>,
+        _: Self::Version,
+        _: Option<Self::InParams>,
+        _: &InContext,
+    ) -> Result<InOutput<Self::Version, Self::InMetadata>, Error> {
+        Ok(InOutput {
+            version: NumberedVersion { number: Some(3) },
+            metadata: None,
+        })
+    }
+}
+
+#[derive(Serialize, IntoMetadataKV)]
+struct BrokenMetadata {
+    sizes: std::collections::BTreeMap<(u8, u8), u64>,
+}
+
+test_resource!(BrokenMetadataResource {
+    type Version = Version;
+    type Source = Empty;
+    type InParams = Empty;
+    type InMetadata = BrokenMetadata;
+    type OutParams = Empty;
+    type OutMetadata = Empty;
+});
+
+impl Steps for BrokenMetadataResource {
+    fn get(
+        _: Option<Self::Source>,
+        version: Self::Version,
+        _: Option<Self::InParams>,
+        _: &InContext,
+    ) -> Result<InOutput<Self::Version, Self::InMetadata>, Error> {
+        let mut sizes = std::collections::BTreeMap::new();
+        sizes.insert((1, 2), 3);
+        Ok(InOutput {
+            version,
+            metadata: Some(BrokenMetadata { sizes }),
+        })
+    }
+}
+
+test_resource!(VerboseResource {
+    type Version = Version;
+    type Source = Empty;
+    type InParams = Empty;
+    type InMetadata = MetadataBuilder;
+    type OutParams = Empty;
+    type OutMetadata = Empty;
+    const METADATA_LIMITS: MetadataLimits = MetadataLimits {
+        max_entries: Some(2),
+        max_value_length: Some(40),
+        ..MetadataLimits::RECOMMENDED
+    };
+});
+
+impl Steps for VerboseResource {
+    fn get(
+        _: Option<Self::Source>,
+        version: Self::Version,
+        _: Option<Self::InParams>,
+        _: &InContext,
+    ) -> Result<InOutput<Self::Version, Self::InMetadata>, Error> {
+        let mut metadata = MetadataBuilder::new();
+        metadata
+            .push(
+                "changelog",
+                "\u{1b}[1;31mfixed\u{1b}[0m the \u{1b}]8;;https://ci.example.com\u{7}build\u{1b}]8;;\u{1b}\\",
+            )
+            .push("description", "fixed the build for good, and the tests too")
+            .push("files", "12")
+            .push("lines", "340");
+        Ok(InOutput {
+            version,
+            metadata: Some(metadata),
+        })
+    }
+}
+
+test_resource!(PersistingResource {
+    type Version = Version;
+    type Source = Empty;
+    type InParams = Empty;
+    type InMetadata = MetadataBuilder;
+    type OutParams = Empty;
+    type OutMetadata = Empty;
+    const IN_OUTPUT_FILES: Option<&'static str> = Some(".resource");
+});
+
+impl Steps for PersistingResource {
+    fn get(
+        _: Option<Self::Source>,
+        version: Self::Version,
+        _: Option<Self::InParams>,
+        _: &InContext,
+    ) -> Result<InOutput<Self::Version, Self::InMetadata>, Error> {
+        let mut metadata = MetadataBuilder::new();
+        metadata
+            .push("artifact", "app.tar.gz")
+            .push("artifact", "app.sha256")
+            .push("commit/sha", "abc123");
+        Ok(InOutput {
+            version,
+            metadata: Some(metadata),
+        })
+    }
+}
+
+fn run_test_resource(args: Vec<&str>, stdin: &str) -> (ExitCode, String, String) {
+    run_resource::<TestResource>(args, stdin)
+}
+
+fn run_resource<R: TryResource>(args: Vec<&str>, stdin: &str) -> (ExitCode, String, String) {
+    let mut stdout = vec![];
+    let mut stderr = vec![];
+    let exit_code = run::<R>(
+        args,
+        stdin.as_bytes(),
+        &mut stdout,
+        &mut stderr,
+        vec![
+            ("BUILD_ID", "42"),
+            ("BUILD_TEAM_NAME", "main"),
+            ("ATC_EXTERNAL_URL", "https://ci.example.com"),
+        ],
+    );
+    (
+        exit_code,
+        String::from_utf8(stdout).unwrap(),
+        String::from_utf8(stderr).unwrap(),
+    )
+}
+
+#[test]
+fn check_returns_versions() {
+    let (exit_code, stdout, stderr) = run_test_resource(vec!["/opt/resource/check"], r#"{}"#);
+
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(stdout, "[{\"ver\":\"1\"}]\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn check_error_exits_with_failure() {
+    let (exit_code, stdout, stderr) = run_test_resource(
+        vec!["/opt/resource/check"],
+        r#"{"version":{"ver":"broken"}}"#,
+    );
+
+    assert_eq!(exit_code, ExitCode::FAILURE);
+    assert_eq!(stdout, "");
+    assert_eq!(stderr, "error: upstream is broken\n");
+}
+
+#[test]
+fn missing_build_metadata_is_an_internal_error() {
+    let mut stdout = vec![];
+    let mut stderr = vec![];
+    let exit_code = run::<TestResource>(
+        vec!["in", "/tmp/build/get"],
+        r#"{"version":{"ver":"1"}}"#.as_bytes(),
+        &mut stdout,
+        &mut stderr,
+        Vec::<(String, String)>::new(),
+    );
+
+    assert_eq!(exit_code, ExitCode::from(70));
+    assert_eq!(stdout, b"");
+    assert_eq!(
+        String::from_utf8(stderr).unwrap(),
+        "fetching\nerror: internal error: environment variable BUILD_ID should be present\n"
+    );
+}
+
+#[test]
+fn in_uses_injected_environment() {
+    let (exit_code, stdout, stderr) = run_test_resource(
+        vec!["/opt/resource/in", "/tmp/build/get"],
+        r#"{"version":{"ver":"1"}}"#,
+    );
+
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(
+        stdout,
+        "{\"version\":{\"ver\":\"1\"},\"metadata\":[{\"name\":\"build\",\"value\":\"42\"}]}\n"
+    );
+    assert_eq!(stderr, "fetching\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_environment_is_skipped() {
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+    let mut stdout = vec![];
+    let exit_code = run::<TestResource>(
+        vec!["in", "/tmp/build/get"],
+        r#"{"version":{"ver":"1"}}"#.as_bytes(),
+        &mut stdout,
+        std::io::sink(),
+        vec![
+            (OsString::from("BAD"), OsString::from_vec(vec![0xff])),
+            (OsString::from_vec(vec![0xff]), OsString::from("value")),
+            (OsString::from("BUILD_ID"), OsString::from("42")),
+            (OsString::from("BUILD_TEAM_NAME"), OsString::from("main")),
+            (
+                OsString::from("ATC_EXTERNAL_URL"),
+                OsString::from("https://ci.example.com"),
+            ),
+        ],
+    );
+
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(
+        String::from_utf8(stdout).unwrap(),
+        "{\"version\":{\"ver\":\"1\"},\"metadata\":[{\"name\":\"build\",\"value\":\"42\"}]}\n"
+    );
+}
+
+#[test]
+fn environment_is_restored_after_a_panic() {
+    let panicked = std::panic::catch_unwind(|| {
+        run::<TestResource>(
+            vec!["check"],
+            r#"{"version":{"ver":"panic"}}"#.as_bytes(),
+            std::io::sink(),
+            std::io::sink(),
+            vec![("BUILD_ID", "42")],
+        )
+    });
+    assert!(panicked.is_err());
+
+    assert_eq!(
+        Interpolated::new("$BUILD_ID").interpolate_env().unwrap(),
+        std::env::var("BUILD_ID").unwrap_or_default()
+    );
+}
+
+#[test]
+fn out_error_exits_with_failure() {
+    let (exit_code, stdout, stderr) =
+        run_test_resource(vec!["/opt/resource/out", "/tmp/build/put"], r#"{}"#);
+
+    assert_eq!(exit_code, ExitCode::FAILURE);
+    assert_eq!(stdout, "");
+    assert_eq!(stderr, "error: put is not supported\n");
+}
+
+#[test]
+fn dispatch_on_basename() {
+    let (exit_code, stdout, _) = run_test_resource(vec!["./check"], r#"{}"#);
+
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(stdout, "[{\"ver\":\"1\"}]\n");
+}
+
+#[test]
+fn dispatch_on_subcommand() {
+    let (exit_code, stdout, _) = run_test_resource(
+        vec!["target/debug/main", "in", "/tmp/build/get"],
+        r#"{"version":{"ver":"1"}}"#,
+    );
+
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(
+        stdout,
+        "{\"version\":{\"ver\":\"1\"},\"metadata\":[{\"name\":\"build\",\"value\":\"42\"}]}\n"
+    );
+}
+
+#[test]
+fn unknown_invocation_prints_usage() {
+    let (exit_code, stdout, stderr) = run_test_resource(vec!["main", "get"], r#"{}"#);
+
+    assert_eq!(exit_code, ExitCode::from(2));
+    assert_eq!(stdout, "");
+    assert!(stderr.starts_with("unexpected being called as 'main'\n\nusage: main check\n"));
+    assert!(stderr.contains("/opt/resource/check"));
+}
+
+#[test]
+fn install_creates_entrypoints() {
+    let dir = std::env::temp_dir().join(format!("concourse-resource-{}", std::process::id()));
+
+    let (exit_code, _, stderr) =
+        run_test_resource(vec!["main", "install", dir.to_str().unwrap()], "");
+    assert_eq!(exit_code, ExitCode::SUCCESS, "{}", stderr);
+    let exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+    for entrypoint in &["check", "in", "out"] {
+        assert!(std::fs::read(dir.join(entrypoint)).unwrap() == exe);
+    }
+
+    let (exit_code, _, stderr) =
+        run_test_resource(vec!["main", "install", dir.to_str().unwrap()], "");
+    assert_eq!(exit_code, ExitCode::SUCCESS, "{}", stderr);
+    assert!(stderr.contains("is already installed"));
+
+    std::fs::remove_file(dir.join("out")).unwrap();
+    std::fs::write(dir.join("out"), "#!/bin/sh").unwrap();
+    let (exit_code, _, stderr) =
+        run_test_resource(vec!["main", "install", dir.to_str().unwrap()], "");
+    assert_eq!(exit_code, ExitCode::from(70));
+    assert!(stderr.contains("out already exists"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn legacy_resource_is_dispatched() {
+    let (exit_code, stdout, _) = run_resource::<OldResource>(vec!["check"], r#"{}"#);
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(stdout, "[{\"ver\":\"legacy\"}]\n");
+
+    let (exit_code, stdout, _) =
+        run_resource::<OldResource>(vec!["in", "/tmp/build/get"], r#"{"version":{"ver":"1"}}"#);
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(stdout, "{\"version\":{\"ver\":\"1\"},\"metadata\":null}\n");
+
+    let (exit_code, stdout, _) =
+        run_resource::<OldResource>(vec!["out", "/tmp/build/put"], r#"{}"#);
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(
+        stdout,
+        "{\"version\":{\"ver\":\"/tmp/build/put\"},\"metadata\":null}\n"
+    );
+}
+
+#[test]
+fn invalid_version_exits_with_its_class_code() {
+    let (exit_code, stdout, stderr) =
+        run_test_resource(vec!["check"], r#"{"version":{"version":"1"}}"#);
+
+    assert_eq!(exit_code, ExitCode::from(5));
+    assert_eq!(stdout, "");
+    assert_eq!(stderr, "error: invalid version: missing field `ver`\n");
+}
+
+#[test]
+fn missing_destination_prints_usage() {
+    let (exit_code, _, stderr) = run_test_resource(vec!["in"], r#"{"version":{"ver":"1"}}"#);
+
+    assert_eq!(exit_code, ExitCode::from(2));
+    assert!(stderr.contains("usage: in check\n"));
+}
+
+#[derive(Deserialize)]
+struct FilesParams {
+    #[allow(dead_code)]
+    files: Vec<File>,
+}
+
+#[derive(Deserialize)]
+struct File {
+    #[allow(dead_code)]
+    glob: String,
+}
+
+#[derive(Deserialize)]
+struct BranchSource {
+    #[allow(dead_code)]
+    branch: Option<String>,
+}
+
+test_resource!(FilesResource {
+    type Version = Version;
+    type Source = BranchSource;
+    type InParams = Empty;
+    type InMetadata = Empty;
+    type OutParams = FilesParams;
+    type OutMetadata = Empty;
+    const UNKNOWN_SOURCE_KEYS: UnknownKeys = UnknownKeys::Warn;
+    const UNKNOWN_PARAMS_KEYS: UnknownKeys = UnknownKeys::Fail;
+    const RENAMED_SOURCE_KEYS: &'static [RenamedKey] = &[RenamedKey::new("ref", "branch")];
+    const RENAMED_PARAMS_KEYS: &'static [RenamedKey] =
+        &[RenamedKey::new("files[].pattern", "files[].glob")];
+});
+
+impl Steps for FilesResource {
+    fn put(
+        _: Option<Self::Source>,
+        _: Option<Self::OutParams>,
+        _: &OutContext,
+    ) -> Result<OutOutput<Self::Version, Self::OutMetadata>, Error> {
+        Ok(OutOutput {
+            version: Version {
+                ver: String::from("1"),
+            },
+            metadata: None,
+        })
+    }
+}
+
+#[derive(ConcourseVersion)]
+struct BuildVersion {
+    build: u64,
+}
+
+test_resource!(StrictVersionResource {
+    type Version = BuildVersion;
+    type Source = Empty;
+    type InParams = Empty;
+    type InMetadata = Empty;
+    type OutParams = Empty;
+    type OutMetadata = Empty;
+    const UNKNOWN_VERSION_KEYS: UnknownKeys = UnknownKeys::Fail;
+});
+
+impl Steps for StrictVersionResource {
+    fn check(
+        _: Option<Self::Source>,
+        version: Option<Self::Version>,
+        _: &CheckContext,
+    ) -> Result<Vec<Self::Version>, Error> {
+        Ok(version.into_iter().collect())
+    }
+}
+
+#[derive(Deserialize)]
+struct TagParams {
+    #[serde(deserialize_with = "concourse_resource::interpolate::deserialize")]
+    tag: String,
+    #[serde(
+        default,
+        deserialize_with = "concourse_resource::interpolate::deserialize_option"
+    )]
+    message: Option<String>,
+}
+
+#[derive(Serialize, IntoMetadataKV)]
+struct TagMetadata {
+    message: Option<String>,
+}
+
+test_resource!(TaggingResource {
+    type Version = Version;
+    type Source = Empty;
+    type InParams = Empty;
+    type InMetadata = Empty;
+    type OutParams = TagParams;
+    type OutMetadata = TagMetadata;
+});
+
+impl Steps for TaggingResource {
+    fn put(
+        _: Option<Self::Source>,
+        params: Option<Self::OutParams>,
+        _: &OutContext,
+    ) -> Result<OutOutput<Self::Version, Self::OutMetadata>, Error> {
+        let params = params.ok_or("params are required")?;
+        Ok(OutOutput {
+            version: Version { ver: params.tag },
+            metadata: Some(TagMetadata {
+                message: params.message,
+            }),
+        })
+    }
+}
+
+#[test]
+fn invalid_params_point_at_json_path() {
+    let (exit_code, _, stderr) = run_resource::<FilesResource>(
+        vec!["out", "/tmp/build/put"],
+        r#"{"params":{"files":[{"glob":"*.md"},{"glob":"*.rs"},{"glob":3}]}}"#,
+    );
+
+    assert_eq!(exit_code, ExitCode::from(4));
+    assert_eq!(
+        stderr,
+        "error: invalid params: .files[2].glob: invalid type: integer `3`, expected a string\n"
+    );
+}
+
+#[test]
+fn params_are_interpolated_from_the_environment() {
+    let (exit_code, stdout, stderr) = run_resource::<TaggingResource>(
+        vec!["out", "/tmp/build/put"],
+        r#"{"params":{"tag":"build-$BUILD_ID","message":"${BUILD_TEAM_NAME} paid $5"}}"#,
+    );
+    assert_eq!(exit_code, ExitCode::SUCCESS, "{}", stderr);
+    assert_eq!(
+        stdout,
+        "{\"version\":{\"ver\":\"build-42\"},\"metadata\":[{\"name\":\"message\",\"value\":\"main paid $5\"}]}\n"
+    );
+
+    let (exit_code, stdout, _) = run_resource::<TaggingResource>(
+        vec!["out", "/tmp/build/put"],
+        r#"{"params":{"tag":"$BUILD_ID"}}"#,
+    );
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(stdout, "{\"version\":{\"ver\":\"42\"},\"metadata\":[]}\n");
+
+    let (exit_code, _, stderr) = run_resource::<TaggingResource>(
+        vec!["out", "/tmp/build/put"],
+        r#"{"params":{"tag":"$BUILD_NUMBER"}}"#,
+    );
+    assert_eq!(exit_code, ExitCode::from(4));
+    assert!(stderr.starts_with("error: invalid params: .tag: unknown variable $BUILD_NUMBER"));
+}
+
+#[test]
+fn unknown_keys_are_reported() {
+    let (exit_code, _, stderr) = run_resource::<FilesResource>(
+        vec!["out", "/tmp/build/put"],
+        r#"{"source":{"brnach":"main","token":"x"},"params":{"files":[]}}"#,
+    );
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(
+        stderr,
+        "warning: unknown key in source: .brnach (did you mean `branch`?)\nwarning: unknown key in source: .token\n"
+    );
+
+    let (exit_code, _, stderr) = run_resource::<FilesResource>(
+        vec!["out", "/tmp/build/put"],
+        r#"{"params":{"file":[],"files":[{"glob":"*","globs":"*.rs"}]}}"#,
+    );
+    assert_eq!(exit_code, ExitCode::from(4));
+    assert_eq!(
+        stderr,
+        "error: invalid params: unknown keys .file (did you mean `files`?), .files[0].globs (did you mean `glob`?)\n"
+    );
+}
+
+#[test]
+fn unknown_keys_of_derived_versions_are_reported() {
+    let (exit_code, stdout, _) =
+        run_resource::<StrictVersionResource>(vec!["check"], r#"{"version":{"build":"3"}}"#);
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(stdout, "[{\"build\":\"3\"}]\n");
+
+    let (exit_code, _, stderr) = run_resource::<StrictVersionResource>(
+        vec!["check"],
+        r#"{"version":{"build":"3","buld":"4"}}"#,
+    );
+    assert_eq!(exit_code, ExitCode::from(5));
+    assert_eq!(
+        stderr,
+        "error: invalid version: unknown keys .buld (did you mean `build`?)\n"
+    );
+}
+
+#[test]
+fn renamed_keys_are_migrated() {
+    let (exit_code, _, stderr) = run_resource::<FilesResource>(
+        vec!["out", "/tmp/build/put"],
+        r#"{"source":{"ref":"main"},"params":{"files":[]}}"#,
+    );
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(
+        stderr,
+        "warning: `ref` in source is deprecated, use `branch` instead\n"
+    );
+
+    let (exit_code, _, stderr) = run_resource::<FilesResource>(
+        vec!["out", "/tmp/build/put"],
+        r#"{"source":{"ref":"main","branch":"dev"},"params":{"files":[]}}"#,
+    );
+    assert_eq!(exit_code, ExitCode::from(3));
+    assert_eq!(
+        stderr,
+        "error: invalid source: both `ref` and `branch` are set, `ref` is the deprecated name of `branch`\n"
+    );
+    let (exit_code, _, stderr) = run_resource::<FilesResource>(
+        vec!["out", "/tmp/build/put"],
+        r#"{"params":{"files":[{"pattern":"*.md"},{"glob":"*.rs"},{"pattern":"*.toml"}]}}"#,
+    );
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(
+        stderr,
+        "warning: `files[].pattern` in params is deprecated, use `files[].glob` instead\n"
+    );
+
+    let (exit_code, _, stderr) = run_resource::<FilesResource>(
+        vec!["out", "/tmp/build/put"],
+        r#"{"params":{"files":[{"pattern":"*.md","glob":"*.rs"}]}}"#,
+    );
+    assert_eq!(exit_code, ExitCode::from(4));
+    assert_eq!(
+        stderr,
+        "error: invalid params: both `files[].pattern` and `files[].glob` are set, `files[].pattern` is the deprecated name of `files[].glob`\n"
+    );
+}
+
+#[test]
+fn invalid_responses_are_rejected() {
+    let (exit_code, stdout, stderr) = run_resource::<InvalidOutputResource>(vec!["check"], "{}");
+    assert_eq!(exit_code, ExitCode::from(70));
+    assert_eq!(stdout, "");
+    assert_eq!(
+        stderr,
+        "error: internal error: invalid response, `[0].number` is null, but should be a string, versions are flat objects of string values\n"
+    );
+
+    let (exit_code, _, stderr) =
+        run_resource::<InvalidOutputResource>(vec!["in", "/tmp/build/get"], r#"{"version":{}}"#);
+    assert_eq!(exit_code, ExitCode::from(70));
+    assert_eq!(
+        stderr,
+        "error: internal error: invalid response, `.version.number` is a number, but should be a string, versions are flat objects of string values\n"
+    );
+}
+
+#[test]
+fn metadata_errors_fail_the_step() {
+    let (exit_code, stdout, stderr) = run_resource::<BrokenMetadataResource>(
+        vec!["in", "/tmp/build/get"],
+        r#"{"version":{"ver":"1"}}"#,
+    );
+    assert_eq!(exit_code, ExitCode::from(70));
+    assert_eq!(stdout, "");
+    assert_eq!(
+        stderr,
+        "error: internal error: could not serialize metadata `sizes`\ncaused by: key must be a string\n"
+    );
+}
+
+#[test]
+fn metadata_is_limited() {
+    let (exit_code, stdout, stderr) =
+        run_resource::<VerboseResource>(vec!["in", "/tmp/build/get"], r#"{"version":{"ver":"1"}}"#);
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+    assert_eq!(
+        stdout,
+        "{\"version\":{\"ver\":\"1\"},\"metadata\":[{\"name\":\"changelog\",\"value\":\"fixed the build\"},{\"name\":\"description\",\"value\":\"fixed the bui… (30 characters truncated)\"}]}\n"
+    );
+    assert_eq!(
+        stderr,
+        "warning: metadata has too many entries, the last 2 were dropped\nwarning: metadata `description` is too long and was truncated\n"
+    );
+}
+
+#[test]
+fn in_output_is_persisted() {
+    let dir =
+        std::env::temp_dir().join(format!("concourse-resource-persist-{}", std::process::id()));
+    let (exit_code, _, stderr) = run_resource::<PersistingResource>(
+        vec!["in", dir.to_str().unwrap()],
+        r#"{"version":{"ver":"1"}}"#,
+    );
+    assert_eq!(stderr, "");
+    assert_eq!(exit_code, ExitCode::SUCCESS);
+
+    let read = |path: &str| std::fs::read_to_string(dir.join(".resource").join(path)).unwrap();
+    assert_eq!(read("version.json"), r#"{"ver":"1"}"#);
+    assert_eq!(
+        read("metadata.json"),
+        r#"[{"name":"artifact","value":"app.tar.gz"},{"name":"artifact","value":"app.sha256"},{"name":"commit/sha","value":"abc123"}]"#
+    );
+    assert_eq!(read("version/ver"), "1");
+    assert_eq!(read("metadata/artifact"), "app.tar.gz\napp.sha256");
+    assert_eq!(read("metadata/commit_sha"), "abc123");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[derive(Debug)]
+struct ConfigError(std::num::ParseIntError);
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "could not read `retries`")
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+#[test]
+fn error_report() {
+    let error = Error::invalid_params(ConfigError("three".parse::<u8>().unwrap_err()))
+        .with_hint("`retries` should be a number");
+
+    assert_eq!(error.exit_code(), 4);
+    assert_eq!(
+        error.to_string(),
+        "invalid params: could not read `retries`"
+    );
+    assert_eq!(
+        format!("{:#}", error),
+        "invalid params: could not read `retries`\ncaused by: invalid digit found in string\nhint: `retries` should be a number"
+    );
+}
+
+#[test]
+fn error_is_a_std_error() {
+    fn read_retries() -> Result<u8, Box<dyn std::error::Error>> {
+        let retries = "three"
+            .parse()
+            .map_err(ConfigError)
+            .map_err(Error::invalid_params)?;
+        Ok(retries)
+    }
+
+    let error = read_retries().unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "invalid params: could not read `retries`"
+    );
+    assert_eq!(
+        error.source().unwrap().to_string(),
+        "could not read `retries`"
+    );
+}
@@ -0,0 +1,102 @@
+#![no_implicit_prelude]
+
+extern crate concourse_resource;
+extern crate serde;
+extern crate serde_json;
+extern crate std;
+
+use concourse_resource as resource;
+
+mod metadata {
+    #[derive(::serde::Serialize, ::concourse_resource::IntoMetadataKV)]
+    pub struct Metadata {
+        pub commit: ::std::string::String,
+        #[metadata(join = ", ")]
+        pub labels: ::std::vec::Vec<::std::string::String>,
+        #[metadata(display)]
+        pub count: u32,
+    }
+
+    #[derive(::concourse_resource::IntoMetadataKV)]
+    #[metadata(crate = "super::resource")]
+    pub enum Event {
+        Push(::std::string::String),
+    }
+
+    // named like locals of the code generated by the derive
+    pub fn md(count: &u32) -> ::std::string::String {
+        ::std::format!("{} items", count)
+    }
+
+    pub fn element(label: &::std::string::String) -> ::std::string::String {
+        ::std::string::ToString::to_string(label)
+    }
+
+    pub fn value(tag: &::std::string::String) -> ::std::string::String {
+        ::std::format!("#{}", tag)
+    }
+
+    pub fn values(tags: &[::std::string::String]) -> bool {
+        tags.is_empty()
+    }
+
+    #[derive(::concourse_resource::IntoMetadataKV)]
+    pub struct Shadowing {
+        #[metadata(with = "md")]
+        pub count: u32,
+        #[metadata(each, with = "element")]
+        pub labels: ::std::vec::Vec<::std::string::String>,
+        #[metadata(join = ", ", with = "value", skip_if = "values")]
+        pub tags: ::std::vec::Vec<::std::string::String>,
+    }
+
+    #[derive(::concourse_resource::ConcourseVersion)]
+    #[version(crate = "super::resource")]
+    pub struct Version {
+        pub build: u64,
+    }
+}
+
+#[test]
+fn derives_do_not_depend_on_imports() {
+    use std::{assert_eq, string::ToString, vec};
+
+    use resource::IntoMetadataKV;
+
+    let metadata = metadata::Metadata {
+        commit: "abc123".to_string(),
+        labels: vec!["bug".to_string()],
+        count: 2,
+    }
+    .into_metadata_kv();
+    assert_eq!(metadata.len(), 3);
+    assert_eq!(metadata[2].value, "2");
+
+    let event = metadata::Event::Push("main".to_string()).into_metadata_kv();
+    assert_eq!(event[1].value, "main");
+
+    let version = metadata::Version { build: 12 };
+    assert_eq!(
+        serde_json::to_string(&version).unwrap(),
+        r#"{"build":"12"}"#
+    );
+}
+
+#[test]
+fn derive_locals_do_not_shadow_functions() {
+    use std::{assert_eq, string::ToString, vec};
+
+    use resource::IntoMetadataKV;
+
+    let metadata = metadata::Shadowing {
+        count: 2,
+        labels: vec!["bug".to_string()],
+        tags: vec!["v1".to_string(), "latest".to_string()],
+    }
+    .into_metadata_kv();
+    assert_eq!(metadata.len(), 3);
+    assert_eq!(metadata[0].value, "2 items");
+    assert_eq!(metadata[1].name, "labels[0]");
+    assert_eq!(metadata[1].value, "bug");
+    assert_eq!(metadata[2].value, "#v1, #latest");
+}
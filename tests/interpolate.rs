@@ -0,0 +1,59 @@
+use concourse_resource::{interpolate::InterpolationError, BuildMetadata, Interpolated};
+
+fn metadata() -> BuildMetadata {
+    BuildMetadata::builder()
+        .id("42")
+        .pipeline_name("release")
+        .build()
+}
+
+#[test]
+fn interpolate_variables() {
+    assert_eq!(
+        Interpolated::new("$BUILD_PIPELINE_NAME-$BUILD_ID/${BUILD_TEAM_NAME}_x")
+            .interpolate(&metadata())
+            .unwrap(),
+        "release-42/main_x"
+    );
+    assert_eq!(
+        Interpolated::new("job: $BUILD_JOB_NAME.")
+            .interpolate(&metadata())
+            .unwrap(),
+        "job: ."
+    );
+}
+
+#[test]
+fn escape_dollar() {
+    assert_eq!(
+        Interpolated::new("$$BUILD_ID costs 5$ or $ 6")
+            .interpolate(&metadata())
+            .unwrap(),
+        "$BUILD_ID costs 5$ or $ 6"
+    );
+    assert_eq!(
+        Interpolated::new("costs $5, $-1 or $.")
+            .interpolate(&metadata())
+            .unwrap(),
+        "costs $5, $-1 or $."
+    );
+}
+
+#[test]
+fn unknown_variable_is_an_error() {
+    let error = Interpolated::new("${BUILD_NUMBER}")
+        .interpolate(&metadata())
+        .unwrap_err();
+    assert_eq!(
+        error,
+        InterpolationError::UnknownVariable(String::from("BUILD_NUMBER"))
+    );
+    assert!(error
+        .to_string()
+        .starts_with("unknown variable $BUILD_NUMBER"));
+
+    assert_eq!(
+        Interpolated::new("${BUILD_ID").interpolate(&metadata()),
+        Err(InterpolationError::Unterminated)
+    );
+}
@@ -0,0 +1,321 @@
+use std::collections::{BTreeMap, HashMap};
+
+use concourse_resource::{internal::KV, IntoMetadataKV, MetadataBuilder};
+
+use serde::Serialize;
+
+fn pairs(metadata: Vec<KV>) -> Vec<(String, String)> {
+    metadata.into_iter().map(|kv| (kv.name, kv.value)).collect()
+}
+
+#[derive(Serialize, IntoMetadataKV)]
+#[metadata(rename_all = "Title Case")]
+struct Metadata {
+    commit_sha: String,
+    #[metadata(rename = "MR")]
+    mr_iid: u64,
+    #[metadata(skip)]
+    token: String,
+    #[metadata(skip_if = "Option::is_none")]
+    reviewer: Option<String>,
+}
+
+#[test]
+fn metadata_attributes_are_applied() {
+    let metadata = Metadata {
+        commit_sha: String::from("abc123"),
+        mr_iid: 12,
+        token: String::from("secret"),
+        reviewer: None,
+    };
+    assert_eq!(
+        pairs(metadata.into_metadata_kv()),
+        vec![
+            (String::from("Commit Sha"), String::from("abc123")),
+            (String::from("MR"), String::from("12")),
+        ]
+    );
+}
+
+#[derive(Serialize, IntoMetadataKV)]
+#[serde(rename_all = "kebab-case")]
+struct SerdeMetadata {
+    commit_sha: String,
+    #[serde(rename = "author")]
+    author_name: String,
+    #[serde(rename = "id")]
+    #[metadata(rename = "build id")]
+    build_id: String,
+    #[allow(dead_code)]
+    #[serde(skip)]
+    token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reviewer: Option<String>,
+}
+
+#[test]
+fn serde_attributes_are_honored() {
+    let metadata = SerdeMetadata {
+        commit_sha: String::from("abc123"),
+        author_name: String::from("Han Solo"),
+        build_id: String::from("42"),
+        token: String::from("secret"),
+        reviewer: Some(String::from("Leia")),
+    };
+    assert_eq!(
+        pairs(metadata.into_metadata_kv()),
+        vec![
+            (String::from("commit-sha"), String::from("abc123")),
+            (String::from("author"), String::from("Han Solo")),
+            (String::from("build id"), String::from("42")),
+            (String::from("reviewer"), String::from("Leia")),
+        ]
+    );
+}
+
+fn shout(value: &str) -> String {
+    value.to_uppercase()
+}
+
+#[derive(Serialize, IntoMetadataKV)]
+struct FormattedMetadata {
+    message: String,
+    reviewer: Option<String>,
+    labels: Vec<String>,
+    #[metadata(display)]
+    address: std::net::Ipv4Addr,
+    #[metadata(with = "shout")]
+    status: String,
+}
+
+#[test]
+fn metadata_values_are_formatted() {
+    let metadata = FormattedMetadata {
+        message: String::from("fix \"quotes\"\nand newlines"),
+        reviewer: None,
+        labels: vec![String::from("bug")],
+        address: std::net::Ipv4Addr::LOCALHOST,
+        status: String::from("ok"),
+    };
+    assert_eq!(
+        pairs(metadata.into_metadata_kv()),
+        vec![
+            (
+                String::from("message"),
+                String::from("fix \"quotes\"\nand newlines")
+            ),
+            (String::from("labels"), String::from(r#"["bug"]"#)),
+            (String::from("address"), String::from("127.0.0.1")),
+            (String::from("status"), String::from("OK")),
+        ]
+    );
+}
+
+#[derive(Serialize, IntoMetadataKV)]
+struct Commit {
+    sha: String,
+    author: String,
+}
+
+#[derive(Serialize, IntoMetadataKV)]
+struct NestedMetadata {
+    #[metadata(flatten)]
+    commit: Commit,
+    #[metadata(flatten, prefix = "parent ")]
+    parent: Commit,
+    #[metadata(each)]
+    files: Vec<String>,
+    #[metadata(join = ", ")]
+    labels: Vec<String>,
+    #[metadata(join = ", ", display)]
+    reviewers: Vec<std::net::Ipv4Addr>,
+}
+
+#[test]
+fn nested_metadata_is_flattened() {
+    let metadata = NestedMetadata {
+        commit: Commit {
+            sha: String::from("abc123"),
+            author: String::from("Han Solo"),
+        },
+        parent: Commit {
+            sha: String::from("def456"),
+            author: String::from("Leia"),
+        },
+        files: vec![String::from("src/lib.rs"), String::from("README.md")],
+        labels: vec![String::from("bug"), String::from("ui")],
+        reviewers: vec![],
+    };
+    assert_eq!(
+        pairs(metadata.into_metadata_kv()),
+        vec![
+            (String::from("commit.sha"), String::from("abc123")),
+            (String::from("commit.author"), String::from("Han Solo")),
+            (String::from("parent sha"), String::from("def456")),
+            (String::from("parent author"), String::from("Leia")),
+            (String::from("files[0]"), String::from("src/lib.rs")),
+            (String::from("files[1]"), String::from("README.md")),
+            (String::from("labels"), String::from("bug, ui")),
+        ]
+    );
+}
+
+#[derive(Serialize, IntoMetadataKV)]
+struct GenericMetadata<T> {
+    value: T,
+}
+
+#[derive(IntoMetadataKV)]
+struct GenericFormattedMetadata<D, E, F> {
+    #[metadata(display)]
+    displayed: D,
+    #[metadata(display, each)]
+    elements: Vec<E>,
+    #[metadata(flatten)]
+    inner: F,
+}
+
+#[derive(Serialize, IntoMetadataKV)]
+struct TupleMetadata(String, #[metadata(rename = "count")] u32);
+
+#[derive(Serialize, IntoMetadataKV)]
+#[serde(rename_all = "lowercase")]
+#[metadata(tag = "event")]
+enum EventMetadata {
+    Push {
+        branch: String,
+    },
+    #[metadata(rename = "merge request")]
+    MergeRequest(u64, #[metadata(skip)] String),
+    #[serde(skip)]
+    Internal,
+}
+
+#[derive(IntoMetadataKV)]
+enum NeverMetadata {}
+
+#[test]
+fn generics_tuples_and_enums_are_supported() {
+    assert_eq!(
+        pairs(GenericMetadata { value: 3 }.into_metadata_kv()),
+        vec![(String::from("value"), String::from("3"))]
+    );
+    assert_eq!(
+        pairs(
+            GenericFormattedMetadata {
+                displayed: 1.5,
+                elements: vec!['a'],
+                inner: GenericMetadata { value: "nested" },
+            }
+            .into_metadata_kv()
+        ),
+        vec![
+            (String::from("displayed"), String::from("1.5")),
+            (String::from("elements[0]"), String::from("a")),
+            (String::from("inner.value"), String::from("nested")),
+        ]
+    );
+    assert_eq!(
+        pairs(TupleMetadata(String::from("first"), 2).into_metadata_kv()),
+        vec![
+            (String::from("0"), String::from("first")),
+            (String::from("count"), String::from("2")),
+        ]
+    );
+    assert_eq!(
+        pairs(
+            EventMetadata::Push {
+                branch: String::from("main")
+            }
+            .into_metadata_kv()
+        ),
+        vec![
+            (String::from("event"), String::from("push")),
+            (String::from("branch"), String::from("main")),
+        ]
+    );
+    assert_eq!(
+        pairs(EventMetadata::MergeRequest(12, String::from("token")).into_metadata_kv()),
+        vec![
+            (String::from("event"), String::from("merge request")),
+            (String::from("0"), String::from("12")),
+        ]
+    );
+    assert!(EventMetadata::Internal.into_metadata_kv().is_empty());
+    assert!(None::<NeverMetadata>.into_metadata_kv().is_empty());
+}
+
+#[test]
+fn std_types_are_metadata() {
+    let mut hash_map = HashMap::new();
+    hash_map.insert("b", String::from("2"));
+    hash_map.insert("a", String::from("1"));
+    hash_map.insert("c", String::from("3"));
+    let expected = vec![
+        (String::from("a"), String::from("1")),
+        (String::from("b"), String::from("2")),
+        (String::from("c"), String::from("3")),
+    ];
+    assert_eq!(pairs(hash_map.into_metadata_kv()), expected);
+
+    let btree_map: BTreeMap<_, _> = expected.iter().cloned().collect();
+    assert_eq!(pairs(btree_map.into_metadata_kv()), expected);
+
+    let reversed: Vec<_> = expected.iter().rev().cloned().collect();
+    assert_eq!(pairs(reversed.clone().into_metadata_kv()), reversed);
+
+    let json = serde_json::json!({"name": "app", "size": 12, "checksum": null});
+    assert_eq!(
+        pairs(json.as_object().unwrap().clone().into_metadata_kv()),
+        vec![
+            (String::from("name"), String::from("app")),
+            (String::from("size"), String::from("12")),
+        ]
+    );
+
+    assert!(None::<Vec<(String, String)>>.into_metadata_kv().is_empty());
+}
+
+#[test]
+fn metadata_builder_keeps_entries_in_order() {
+    let mut metadata = MetadataBuilder::new();
+    assert!(metadata.is_empty());
+    for artifact in &["app.tar.gz", "app.sha256"] {
+        metadata.push("artifact", *artifact);
+    }
+    metadata.extend(vec![("uploaded", "2")]);
+    assert_eq!(
+        serde_json::to_string(&metadata).unwrap(),
+        r#"[{"name":"artifact","value":"app.tar.gz"},{"name":"artifact","value":"app.sha256"},{"name":"uploaded","value":"2"}]"#
+    );
+    assert_eq!(metadata.into_metadata_kv().len(), 3);
+}
+
+#[derive(Serialize, IntoMetadataKV)]
+struct Checksums {
+    by_chunk: BTreeMap<Vec<u8>, String>,
+}
+
+#[derive(Serialize, IntoMetadataKV)]
+struct UploadMetadata {
+    name: String,
+    #[metadata(flatten)]
+    checksums: Checksums,
+}
+
+#[test]
+fn serialization_errors_name_the_field() {
+    let mut by_chunk = BTreeMap::new();
+    by_chunk.insert(vec![0], String::from("abc"));
+    let metadata = UploadMetadata {
+        name: String::from("app.tar.gz"),
+        checksums: Checksums { by_chunk },
+    };
+    let error = metadata.try_into_metadata_kv().unwrap_err();
+    assert_eq!(error.field(), "checksums.by_chunk");
+    assert_eq!(
+        error.to_string(),
+        "could not serialize metadata `checksums.by_chunk`"
+    );
+    assert_eq!(error.cause().to_string(), "key must be a string");
+}
@@ -0,0 +1,102 @@
+//! Run without the test harness: called with a step, this binary is a resource built by
+//! `create_resource!`, otherwise it runs itself as that resource and checks its output.
+
+use std::{
+    io::Write,
+    process::{Command, ExitCode, Stdio},
+};
+
+use concourse_resource::*;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct Version {
+    ver: String,
+}
+
+struct PrintingResource;
+
+impl TryResource for PrintingResource {
+    type Version = Version;
+
+    type Source = Empty;
+
+    type InParams = Empty;
+    type InMetadata = Empty;
+
+    type OutParams = Empty;
+    type OutMetadata = Empty;
+
+    fn try_resource_check(
+        _: Option<Self::Source>,
+        _: Option<Self::Version>,
+        _: &CheckContext,
+    ) -> Result<Vec<Self::Version>, Error> {
+        Err("check is not supported".into())
+    }
+
+    fn try_resource_in(
+        _: Option<Self::Source>,
+        version: Self::Version,
+        _: Option<Self::InParams>,
+        _: &InContext,
+    ) -> Result<InOutput<Self::Version, Self::InMetadata>, Error> {
+        println!("fetching {}", version.ver);
+        Ok(InOutput {
+            version,
+            metadata: None,
+        })
+    }
+
+    fn try_resource_out(
+        _: Option<Self::Source>,
+        _: Option<Self::OutParams>,
+        _: &OutContext,
+    ) -> Result<OutOutput<Self::Version, Self::OutMetadata>, Error> {
+        Err("put is not supported".into())
+    }
+}
+
+fn resource_main() -> ExitCode {
+    create_resource!(PrintingResource);
+    main()
+}
+
+#[cfg(unix)]
+fn println_in_a_step_does_not_corrupt_the_response() {
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .args(["in", "/tmp/build/get"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(br#"{"version":{"ver":"1"}}"#)
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{\"version\":{\"ver\":\"1\"},\"metadata\":null}\n"
+    );
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "fetching 1\n");
+}
+
+fn main() -> ExitCode {
+    if std::env::args_os().nth(1).is_some_and(|step| step == "in") {
+        return resource_main();
+    }
+
+    #[cfg(unix)]
+    {
+        println_in_a_step_does_not_corrupt_the_response();
+        println!("test println_in_a_step_does_not_corrupt_the_response ... ok");
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,99 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use concourse_resource::{InputDir, OutputDir};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "concourse-resource-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn join_stays_inside_the_directory() {
+    let dir = OutputDir::new("/tmp/build/get");
+
+    assert_eq!(
+        dir.join("a/./b/../c").unwrap(),
+        Path::new("/tmp/build/get/a/c")
+    );
+    assert_eq!(dir.join("").unwrap(), Path::new("/tmp/build/get"));
+    assert_eq!(dir.join("a/..").unwrap(), Path::new("/tmp/build/get"));
+
+    let error = dir.join("a/../../secrets").unwrap_err();
+    assert_eq!(error.path(), Path::new("a/../../secrets"));
+    assert_eq!(
+        error.to_string(),
+        "path `a/../../secrets` is outside of the step directory"
+    );
+    assert!(dir.join("/etc/passwd").is_err());
+    assert!(InputDir::new("/tmp/build/put").join("..").is_err());
+}
+
+#[test]
+fn output_dir_writes_files() {
+    let root = temp_dir("output-dir");
+    let dir = OutputDir::new(&root);
+
+    dir.write("bin/data", [0u8, 159, 146, 150]).unwrap();
+    dir.write_text("release/tag", "v1").unwrap();
+    dir.write_text("release/tag", "v2").unwrap();
+    let mut version = BTreeMap::new();
+    version.insert("tag", "v2");
+    dir.write_json("version.json", &version).unwrap();
+
+    assert_eq!(fs::read(root.join("bin/data")).unwrap(), [0, 159, 146, 150]);
+    assert_eq!(fs::read_to_string(root.join("release/tag")).unwrap(), "v2");
+    assert_eq!(
+        fs::read_to_string(root.join("version.json")).unwrap(),
+        r#"{"tag":"v2"}"#
+    );
+    let release: Vec<_> = fs::read_dir(root.join("release"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(release, [OsString::from("tag")]);
+
+    let error = dir.write_text("../escaped", "nope").unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert!(!root.parent().unwrap().join("escaped").exists());
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn input_dir_reads_artifacts() {
+    let root = temp_dir("input-dir");
+    fs::create_dir_all(root.join("repo")).unwrap();
+    fs::create_dir_all(root.join("artifacts/build")).unwrap();
+    fs::write(root.join("repo/version"), "1.2.0").unwrap();
+    fs::write(root.join("artifacts/build/info.json"), r#"{"id":42}"#).unwrap();
+    fs::write(root.join("not-an-artifact"), "").unwrap();
+    let dir = InputDir::new(&root);
+
+    assert_eq!(
+        dir.artifacts().unwrap(),
+        [OsString::from("artifacts"), OsString::from("repo")]
+    );
+    assert_eq!(dir.read_text("repo/version").unwrap(), "1.2.0");
+    assert_eq!(dir.read("repo/version").unwrap(), b"1.2.0");
+    let info: BTreeMap<String, u64> = dir.read_json("artifacts/build/info.json").unwrap();
+    assert_eq!(info["id"], 42);
+    assert_eq!(
+        dir.read_text("../input-dir/repo/version")
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::InvalidInput
+    );
+
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,80 @@
+use std::{collections::BTreeMap, convert::TryFrom};
+
+use concourse_resource::*;
+
+#[derive(ConcourseVersion, Debug, PartialEq)]
+struct Version {
+    tag: String,
+    build: u64,
+    r#ref: String,
+}
+
+#[derive(ConcourseVersion, Debug, PartialEq)]
+struct RenamedVersion {
+    #[version(rename = "ref")]
+    reference: String,
+}
+
+fn version() -> Version {
+    Version {
+        tag: String::from("v1.2"),
+        build: 12,
+        r#ref: String::from("abc123"),
+    }
+}
+
+#[test]
+fn serialize_fields_as_strings() {
+    assert_eq!(
+        serde_json::to_value(version()).unwrap(),
+        serde_json::json!({"tag": "v1.2", "build": "12", "ref": "abc123"})
+    );
+}
+
+#[test]
+fn deserialize_fields_from_strings() {
+    let parsed: Version =
+        serde_json::from_str(r#"{"tag":"v1.2","build":"12","ref":"abc123","extra":"x"}"#).unwrap();
+    assert_eq!(parsed, version());
+
+    let error = serde_json::from_str::<Version>(r#"{"tag":"v1.2","build":"twelve","ref":"x"}"#)
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "invalid value `twelve` for field `build`: invalid digit found in string"
+    );
+
+    let error =
+        serde_json::from_str::<Version>(r#"{"tag":"v1.2","build":12,"ref":"x"}"#).unwrap_err();
+    assert!(error.to_string().starts_with("invalid type: integer `12`"));
+}
+
+#[test]
+fn convert_to_and_from_map() {
+    let map = BTreeMap::from(version());
+    assert_eq!(map["build"], "12");
+    assert_eq!(map["ref"], "abc123");
+    assert_eq!(Version::try_from(map).unwrap(), version());
+
+    let mut map = BTreeMap::new();
+    map.insert(String::from("tag"), String::from("v1.2"));
+    assert_eq!(
+        Version::from_version_map(map),
+        Err(VersionError::MissingField("build"))
+    );
+}
+
+#[test]
+fn renamed_fields_use_their_key() {
+    let version = RenamedVersion {
+        reference: String::from("abc123"),
+    };
+    assert_eq!(
+        serde_json::to_value(&version).unwrap(),
+        serde_json::json!({"ref": "abc123"})
+    );
+    assert_eq!(
+        serde_json::from_str::<RenamedVersion>(r#"{"ref":"abc123"}"#).unwrap(),
+        version
+    );
+}